@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use badgine::{pgn, Engine, Limits};
+use cozy_chess::{Board, Move};
+
+const BENCH_ITERATIONS: usize = 5000;
+const DEFAULT_EPD_ITERATIONS: usize = 10_000;
+/// `Seed` 0 would mean a random seed, which is no good for a signature.
+pub const DEFAULT_SEED: u64 = 1;
+
+const BENCH_POSITIONS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+    "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r1bq1rk1/pp2ppbp/2np1np1/8/3NP3/2N1BP2/PPPQ2PP/R3KB1R w KQ - 3 9",
+    "2r3k1/pp3ppp/4p3/3pP3/1P1n4/P2B4/5PPP/2R3K1 b - - 0 25",
+    "6k1/5p2/6p1/8/7p/8/6PP/6K1 b - - 0 40",
+    "8/8/4k3/3p4/3P4/4K3/8/8 w - - 0 50",
+];
+
+/// Searches a fixed set of positions to a fixed number of iterations and reports the total node
+/// count, which changes whenever the search does.
+pub fn bench(seed: u64) {
+    let mut engine = fixed_engine(BENCH_ITERATIONS, seed).unwrap();
+    let mut nodes = 0;
+    let mut time = Duration::ZERO;
+    for fen in BENCH_POSITIONS {
+        engine.new_game();
+        engine
+            .set_position(&Board::from_fen(fen, false).unwrap(), &[])
+            .unwrap();
+        let stats = engine.search(&Limits::default());
+        nodes += stats.nodes;
+        time += stats.elapsed;
+    }
+    let nps = nodes as u128 * 1_000_000 / time.as_micros().max(1);
+    println!("{nodes} nodes {nps} nps");
+}
+
+/// Runs the positions of an EPD test suite and reports how many of them the engine solves, that
+/// is finds one of the `bm` moves and none of the `am` moves.
+pub fn epd(path: &str, iterations: Option<usize>, seed: u64) -> Result<(), String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    let mut engine = fixed_engine(iterations.unwrap_or(DEFAULT_EPD_ITERATIONS), seed)?;
+    let mut solved = 0;
+    let mut total = 0;
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((board, ops)) = parse_epd(line) else {
+            println!("{path}:{}: could not parse '{line}'", i + 1);
+            continue;
+        };
+        let op = |name: &str| ops.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+        let id = op("id").map_or_else(|| format!("line {}", i + 1), str::to_owned);
+        let moves = |name| -> Result<Vec<Move>, String> {
+            op(name)
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(|mv| parse_move(&board, mv).ok_or_else(|| format!("{id}: bad move '{mv}'")))
+                .collect()
+        };
+        let (best, avoid) = match (moves("bm"), moves("am")) {
+            (Ok(best), Ok(avoid)) => (best, avoid),
+            (Err(e), _) | (_, Err(e)) => {
+                println!("{e}");
+                continue;
+            }
+        };
+
+        engine.new_game();
+        engine.set_position(&board, &[])?;
+        engine.search(&Limits::default());
+        let Some(mv) = engine.best_move() else {
+            println!("{id}: no legal moves");
+            continue;
+        };
+        let ok = (best.is_empty() || best.contains(&mv)) && !avoid.contains(&mv);
+        total += 1;
+        solved += ok as usize;
+        println!(
+            "{id}: {} {}",
+            pgn::to_san(&board, mv),
+            if ok { "solved" } else { "failed" }
+        );
+    }
+    println!("solved {solved} of {total}");
+    Ok(())
+}
+
+/// An engine that searches every position for exactly `iterations` iterations with the same seed,
+/// so results don't depend on timing or on what was searched before.
+fn fixed_engine(iterations: usize, seed: u64) -> Result<Engine, String> {
+    let mut engine = Engine::default();
+    engine.set_option("Iterations", &iterations.to_string())?;
+    engine.set_option("Seed", &seed.to_string())?;
+    Ok(engine)
+}
+
+/// Splits an EPD line into the position and its operations.
+fn parse_epd(line: &str) -> Option<(Board, Vec<(String, String)>)> {
+    let mut fields = line.splitn(5, ' ');
+    let fen: Vec<_> = fields.by_ref().take(4).collect();
+    let board = Board::from_fen(&format!("{} 0 1", fen.join(" ")), false).ok()?;
+    let ops = fields
+        .next()
+        .unwrap_or_default()
+        .split(';')
+        .filter_map(|op| {
+            let (name, value) = op.trim().split_once(' ')?;
+            Some((name.to_owned(), value.trim_matches('"').to_owned()))
+        })
+        .collect();
+    Some((board, ops))
+}
+
+/// Parses a move in SAN, which EPD uses, or failing that in UCI notation.
+fn parse_move(board: &Board, mv: &str) -> Option<Move> {
+    let san = mv.trim_end_matches(['+', '#', '!', '?']).replace('0', "O");
+    let mut found = None;
+    board.generate_moves(|moves| {
+        found = moves.into_iter().find(|&m| {
+            pgn::to_san(board, m).trim_end_matches(['+', '#']) == san || m.to_string() == mv
+        });
+        found.is_some()
+    });
+    found
+}
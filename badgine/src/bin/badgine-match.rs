@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::time::Duration;
+
+use badgine::search::{draw_by_insufficient_material, status};
+use badgine::uci::{from_uci_castling, to_uci_castling};
+use badgine::{pgn, Engine, Limits};
+use cozy_chess::{Board, Color, GameStatus, Move};
+
+const USAGE: &str = "usage: badgine-match <engine1> <engine2> [--games <n>] [--openings <file>] \
+    [--pgn <file>] [--nodes <n> | --movetime <ms>] [--option1 <name>=<value>] \
+    [--option2 <name>=<value>]
+
+Each engine is either `builtin` for an in-process badgine or the path to a UCI engine.";
+
+#[derive(Clone, Copy)]
+enum Limit {
+    Nodes(usize),
+    Movetime(Duration),
+}
+
+trait Player {
+    fn name(&self) -> &str;
+    fn new_game(&mut self);
+    /// Picks a move for `board`, which was reached by playing `moves` from `start`.
+    fn best_move(&mut self, start: &Board, moves: &[Move], board: &Board, limit: Limit) -> Move;
+}
+
+fn main() {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    if args.len() < 2 {
+        eprintln!("{USAGE}");
+        std::process::exit(1);
+    }
+    let mut games = 2;
+    let mut openings = vec![Board::default()];
+    let mut pgn_path = None;
+    let mut limit = Limit::Nodes(10_000);
+    let mut engine_options = [vec![], vec![]];
+    let mut flags = args[2..].iter();
+    while let Some(flag) = flags.next() {
+        let value = flags.next().unwrap_or_else(|| {
+            eprintln!("{USAGE}");
+            std::process::exit(1);
+        });
+        match flag.as_str() {
+            "--games" => games = value.parse().unwrap_or_else(|e| fail(value, &e)),
+            "--openings" => openings = load_openings(value),
+            "--pgn" => pgn_path = Some(value.clone()),
+            "--nodes" => limit = Limit::Nodes(value.parse().unwrap_or_else(|e| fail(value, &e))),
+            "--movetime" => {
+                let ms = value.parse().unwrap_or_else(|e| fail(value, &e));
+                limit = Limit::Movetime(Duration::from_millis(ms));
+            }
+            "--option1" | "--option2" => {
+                let (name, option) = value
+                    .split_once('=')
+                    .unwrap_or_else(|| fail(value, &"expected <name>=<value>"));
+                let engine = (flag == "--option2") as usize;
+                engine_options[engine].push((name.to_owned(), option.to_owned()));
+            }
+            _ => fail(flag, &"unknown flag"),
+        }
+    }
+
+    let mut players: Vec<Box<dyn Player>> = args[..2]
+        .iter()
+        .zip(&engine_options)
+        .map(|(spec, options)| -> Box<dyn Player> {
+            match spec.as_str() {
+                "builtin" => Box::new(Builtin::new(options)),
+                path => Box::new(Uci::start(path, options).unwrap_or_else(|e| fail(path, &e))),
+            }
+        })
+        .collect();
+
+    let mut pgn = String::new();
+    let mut wdl = [0; 3];
+    for game in 0..games {
+        // each opening is played twice, once with each engine as white
+        let start = &openings[game / 2 % openings.len()];
+        let first_is_white = game % 2 == 0;
+        let (white, black) = match first_is_white {
+            true => (0, 1),
+            false => (1, 0),
+        };
+        let (moves, result, reason) = play_game(&mut players, white, start, limit);
+
+        let first_score = match (result, first_is_white) {
+            ("1-0", true) | ("0-1", false) => 0,
+            ("1/2-1/2", _) => 1,
+            _ => 2,
+        };
+        wdl[first_score] += 1;
+        println!(
+            "game {}: {} vs {}: {result} ({reason})",
+            game + 1,
+            players[white].name(),
+            players[black].name()
+        );
+
+        pgn += &format!("[Event \"badgine-match\"]\n[Round \"{}\"]\n", game + 1);
+        pgn += &format!("[White \"{}\"]\n", players[white].name());
+        pgn += &format!("[Black \"{}\"]\n", players[black].name());
+        pgn += &format!("[Result \"{result}\"]\n");
+        if start.hash() != Board::default().hash() {
+            pgn += &format!("[SetUp \"1\"]\n[FEN \"{start}\"]\n");
+        }
+        pgn += &format!("[Termination \"{reason}\"]\n\n");
+        let moves: Vec<_> = moves.into_iter().map(|mv| (mv, None)).collect();
+        pgn += &pgn::movetext(start, &moves, result);
+        pgn += "\n";
+    }
+
+    if let Some(path) = pgn_path {
+        std::fs::write(&path, pgn).unwrap_or_else(|e| fail(&path, &e));
+    }
+    let [wins, draws, losses] = wdl;
+    println!(
+        "{} vs {}: +{wins} ={draws} -{losses}",
+        players[0].name(),
+        players[1].name()
+    );
+    println!("{}", elo_summary(wins, draws, losses));
+}
+
+fn fail(context: &str, error: &dyn std::fmt::Display) -> ! {
+    eprintln!("{context}: {error}");
+    std::process::exit(1);
+}
+
+/// Reads an opening suite with one FEN or EPD per line. Move counters are optional, and anything
+/// after the position (such as EPD operations) is ignored.
+fn load_openings(path: &str) -> Vec<Board> {
+    let contents = std::fs::read_to_string(path).unwrap_or_else(|e| fail(path, &e));
+    let mut openings = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let has_clocks = fields.len() >= 6
+            && fields[4].parse::<u32>().is_ok()
+            && fields[5].parse::<u32>().is_ok();
+        let fen = match has_clocks {
+            true => fields[..6].join(" "),
+            false => format!("{} 0 1", fields[..4.min(fields.len())].join(" ")),
+        };
+        match Board::from_fen(&fen, false) {
+            Ok(board) => openings.push(board),
+            Err(e) => eprintln!("{path}:{}: invalid position: {e:?}", i + 1),
+        }
+    }
+    if openings.is_empty() {
+        fail(path, &"no openings found");
+    }
+    openings
+}
+
+/// Plays a game between `players[white]` and the other player. Returns the moves played, the
+/// result and the reason the game ended.
+fn play_game(
+    players: &mut [Box<dyn Player>],
+    white: usize,
+    start: &Board,
+    limit: Limit,
+) -> (Vec<Move>, &'static str, &'static str) {
+    for player in players.iter_mut() {
+        player.new_game();
+    }
+    let mut board = start.clone();
+    let mut moves = vec![];
+    let mut seen = HashMap::<u64, u32>::new();
+    loop {
+        let winner = match !board.side_to_move() {
+            Color::White => "1-0",
+            Color::Black => "0-1",
+        };
+        match status(&board, true) {
+            GameStatus::Won => return (moves, winner, "checkmate"),
+            GameStatus::Drawn if board.halfmove_clock() >= 100 => {
+                return (moves, "1/2-1/2", "fifty-move rule")
+            }
+            GameStatus::Drawn => return (moves, "1/2-1/2", "stalemate"),
+            GameStatus::Ongoing => {}
+        }
+        let count = seen.entry(board.hash()).or_default();
+        *count += 1;
+        if *count >= 3 {
+            return (moves, "1/2-1/2", "threefold repetition");
+        }
+        if draw_by_insufficient_material(&board) {
+            return (moves, "1/2-1/2", "insufficient material");
+        }
+
+        let player = match board.side_to_move() {
+            Color::White => white,
+            Color::Black => 1 - white,
+        };
+        let mv = players[player].best_move(start, &moves, &board, limit);
+        if !board.is_legal(mv) {
+            eprintln!("{} played illegal move {mv}", players[player].name());
+            // whoever just tried to move loses, so the winner is the other side
+            let loser_result = match board.side_to_move() {
+                Color::White => "0-1",
+                Color::Black => "1-0",
+            };
+            return (moves, loser_result, "illegal move");
+        }
+        board.play_unchecked(mv);
+        moves.push(mv);
+    }
+}
+
+fn elo_summary(wins: u32, draws: u32, losses: u32) -> String {
+    let games = (wins + draws + losses) as f64;
+    let score = (wins as f64 + draws as f64 / 2.0) / games;
+    if !(score > 0.0 && score < 1.0) {
+        return format!(
+            "score {:.1}%, elo difference cannot be estimated",
+            score * 100.0
+        );
+    }
+    let elo = |score: f64| -400.0 * (1.0 / score - 1.0).log10();
+    let variance = (wins as f64 * (1.0 - score).powi(2)
+        + draws as f64 * (0.5 - score).powi(2)
+        + losses as f64 * score.powi(2))
+        / games;
+    // 95% confidence interval
+    let margin = 1.96 * (variance / games).sqrt();
+    let high = elo((score + margin).min(1.0 - f64::EPSILON));
+    let low = elo((score - margin).max(f64::EPSILON));
+    format!(
+        "score {:.1}%, elo difference {:+.1} +/- {:.1}",
+        score * 100.0,
+        elo(score),
+        (high - low) / 2.0
+    )
+}
+
+struct Builtin {
+    name: String,
+    engine: Engine,
+}
+
+impl Builtin {
+    fn new(settings: &[(String, String)]) -> Self {
+        let mut engine = Engine::default();
+        let mut name = "badgine".to_owned();
+        for (option, value) in settings {
+            engine
+                .set_option(option, value)
+                .unwrap_or_else(|e| fail(option, &e));
+            name += &format!(" {option}={value}");
+        }
+        Builtin { name, engine }
+    }
+}
+
+impl Player for Builtin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn new_game(&mut self) {
+        self.engine.new_game();
+    }
+
+    fn best_move(&mut self, start: &Board, moves: &[Move], _: &Board, limit: Limit) -> Move {
+        self.engine
+            .set_position(start, moves)
+            .unwrap_or_else(|e| fail(&self.name, &e));
+        if let Some(mv) = self.engine.book_move() {
+            return mv;
+        }
+        let limits = match limit {
+            Limit::Nodes(n) => Limits {
+                nodes: Some(n),
+                ..Default::default()
+            },
+            Limit::Movetime(t) => Limits {
+                movetime: Some(t),
+                ..Default::default()
+            },
+        };
+        self.engine.search(&limits);
+        self.engine.best_move().unwrap()
+    }
+}
+
+struct Uci {
+    name: String,
+    process: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Uci {
+    fn start(path: &str, options: &[(String, String)]) -> Result<Self, String> {
+        let mut process = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| e.to_string())?;
+        let mut uci = Uci {
+            name: path.to_owned(),
+            stdin: process.stdin.take().unwrap(),
+            stdout: BufReader::new(process.stdout.take().unwrap()),
+            process,
+        };
+        uci.send("uci");
+        loop {
+            let line = uci.read_line();
+            if let Some(name) = line.strip_prefix("id name ") {
+                uci.name = name.to_owned();
+            } else if line == "uciok" {
+                break;
+            }
+        }
+        for (name, value) in options {
+            uci.send(&format!("setoption name {name} value {value}"));
+        }
+        Ok(uci)
+    }
+
+    fn send(&mut self, command: &str) {
+        writeln!(self.stdin, "{command}").unwrap_or_else(|e| fail(&self.name, &e));
+    }
+
+    fn read_line(&mut self) -> String {
+        let mut line = String::new();
+        match self.stdout.read_line(&mut line) {
+            Ok(0) => fail(&self.name, &"engine exited unexpectedly"),
+            Ok(_) => line.trim().to_owned(),
+            Err(e) => fail(&self.name, &e),
+        }
+    }
+}
+
+impl Player for Uci {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn new_game(&mut self) {
+        self.send("ucinewgame");
+        self.send("isready");
+        while self.read_line() != "readyok" {}
+    }
+
+    fn best_move(&mut self, start: &Board, moves: &[Move], board: &Board, limit: Limit) -> Move {
+        let mut position = format!("position fen {start} moves");
+        let mut replay = start.clone();
+        for &mv in moves {
+            position += &format!(" {}", to_uci_castling(&replay, mv, false));
+            replay.play_unchecked(mv);
+        }
+        self.send(&position);
+        match limit {
+            Limit::Nodes(n) => self.send(&format!("go nodes {n}")),
+            Limit::Movetime(t) => self.send(&format!("go movetime {}", t.as_millis())),
+        }
+        loop {
+            let line = self.read_line();
+            if let Some(rest) = line.strip_prefix("bestmove ") {
+                let mv = rest.split_whitespace().next().unwrap_or_default();
+                let mv = mv
+                    .parse()
+                    .unwrap_or_else(|_| fail(&self.name, &format!("invalid move '{mv}'")));
+                return from_uci_castling(board, mv, false);
+            }
+        }
+    }
+}
+
+impl Drop for Uci {
+    fn drop(&mut self) {
+        self.send("quit");
+        let _ = self.process.wait();
+    }
+}
@@ -0,0 +1,192 @@
+use badgine::{evaluate, Weights};
+use cozy_chess::{Board, Color};
+
+const USAGE: &str =
+    "usage: badgine-tune <dataset> <output.toml> [--init <weights.toml>] [--passes <n>]";
+
+const PARAMS: &[&str] = &[
+    "pawn",
+    "knight",
+    "bishop",
+    "rook",
+    "queen",
+    "king_space",
+    "pawn_ranks[1]",
+    "pawn_ranks[2]",
+    "pawn_ranks[3]",
+    "pawn_ranks[4]",
+    "pawn_ranks[5]",
+    "pawn_ranks[6]",
+];
+
+fn param(weights: &mut Weights, i: usize) -> &mut i32 {
+    match i {
+        0 => &mut weights.pawn,
+        1 => &mut weights.knight,
+        2 => &mut weights.bishop,
+        3 => &mut weights.rook,
+        4 => &mut weights.queen,
+        5 => &mut weights.king_space,
+        _ => &mut weights.pawn_ranks[i - 5],
+    }
+}
+
+struct Position {
+    board: Board,
+    /// Game result from white's point of view: 1 for a win, 0.5 for a draw, 0 for a loss.
+    result: f64,
+}
+
+fn main() {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    if args.len() < 2 {
+        eprintln!("{USAGE}");
+        std::process::exit(1);
+    }
+    let mut weights = Weights::default();
+    let mut passes = 100;
+    let mut flags = args[2..].iter();
+    while let Some(flag) = flags.next() {
+        let value = flags.next().unwrap_or_else(|| {
+            eprintln!("{USAGE}");
+            std::process::exit(1);
+        });
+        match flag.as_str() {
+            "--init" => weights = Weights::load(value).unwrap_or_else(|e| fail(value, &e)),
+            "--passes" => passes = value.parse().unwrap_or_else(|e| fail(value, &e)),
+            _ => fail(flag, &"unknown flag"),
+        }
+    }
+
+    let contents = std::fs::read_to_string(&args[0]).unwrap_or_else(|e| fail(&args[0], &e));
+    let mut data = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_line(line) {
+            Some(position) => data.push(position),
+            None => eprintln!("{}:{}: could not parse '{line}'", args[0], i + 1),
+        }
+    }
+    println!("loaded {} positions", data.len());
+
+    let k = fit_scale(&data, &weights);
+    println!("scale {k:.4}, error {:.6}", error(&data, &weights, k));
+
+    let mut best = error(&data, &weights, k);
+    let mut step = 16;
+    for pass in 1..=passes {
+        let mut improved = false;
+        for i in 0..PARAMS.len() {
+            for delta in [step, -step] {
+                let mut candidate = weights.clone();
+                *param(&mut candidate, i) += delta;
+                let e = error(&data, &candidate, k);
+                if e < best {
+                    best = e;
+                    weights = candidate;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        println!("pass {pass}: step {step}, error {best:.6}");
+        if !improved {
+            if step == 1 {
+                break;
+            }
+            step /= 2;
+        }
+    }
+
+    for (i, name) in PARAMS.iter().enumerate() {
+        println!("{name} = {}", param(&mut weights, i));
+    }
+    let output = toml::to_string(&weights).unwrap();
+    std::fs::write(&args[1], output).unwrap_or_else(|e| fail(&args[1], &e));
+}
+
+fn fail(context: &str, error: &dyn std::fmt::Display) -> ! {
+    eprintln!("{context}: {error}");
+    std::process::exit(1);
+}
+
+/// Parses a FEN or EPD followed by a game result such as `1-0`, `[0.5]` or `c9 "0-1";`.
+fn parse_line(line: &str) -> Option<Position> {
+    let fields: Vec<_> = line.split_whitespace().collect();
+    let has_clocks =
+        fields.len() >= 6 && fields[4].parse::<u32>().is_ok() && fields[5].parse::<u32>().is_ok();
+    let fen_len = if has_clocks { 6 } else { 4 };
+    let result = fields.get(fen_len..)?.iter().find_map(|field| {
+        match field.trim_matches(|c| matches!(c, '"' | ';' | '[' | ']' | ',')) {
+            "1-0" | "1.0" | "1" => Some(1.0),
+            "1/2-1/2" | "0.5" => Some(0.5),
+            "0-1" | "0.0" | "0" => Some(0.0),
+            _ => None,
+        }
+    })?;
+
+    let mut fen = fields[..fen_len].join(" ");
+    if !has_clocks {
+        fen += " 0 1";
+    }
+    let board = Board::from_fen(&fen, false).ok()?;
+    Some(Position { board, result })
+}
+
+fn sigmoid(eval: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+fn error(data: &[Position], weights: &Weights, k: f64) -> f64 {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = data.len().div_ceil(threads).max(1);
+    let total: f64 = std::thread::scope(|s| {
+        let workers: Vec<_> = data
+            .chunks(chunk_size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|p| {
+                            let eval = evaluate(&p.board, weights) as f64;
+                            let eval = match p.board.side_to_move() {
+                                Color::White => eval,
+                                Color::Black => -eval,
+                            };
+                            (p.result - sigmoid(eval, k)).powi(2)
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+        workers.into_iter().map(|w| w.join().unwrap()).sum()
+    });
+    total / data.len().max(1) as f64
+}
+
+/// Finds the eval scale that best explains the results with the starting weights.
+fn fit_scale(data: &[Position], weights: &Weights) -> f64 {
+    let mut k = 1.0;
+    let mut step = 0.5;
+    let mut best = error(data, weights, k);
+    while step > 0.001 {
+        let mut improved = false;
+        for candidate in [k - step, k + step] {
+            if candidate <= 0.0 {
+                continue;
+            }
+            let e = error(data, weights, candidate);
+            if e < best {
+                best = e;
+                k = candidate;
+                improved = true;
+            }
+        }
+        if !improved {
+            step /= 2.0;
+        }
+    }
+    k
+}
@@ -0,0 +1,49 @@
+use cozy_chess::{Board, Color, Move};
+use rand::Rng;
+
+const MAX_OVERLOOK_CHANCE: f64 = 0.95;
+
+/// Decides whether a move is overlooked when its position is first expanded. Overlooked moves are
+/// never searched, so the engine plays as if they didn't exist, including ones the opponent has.
+pub fn overlooks(
+    board: &Board,
+    mv: Move,
+    gives_check: bool,
+    rate: f64,
+    rng: &mut impl Rng,
+) -> bool {
+    if rate <= 0.0 {
+        return false;
+    }
+    let chance = (rate * difficulty(board, mv, gives_check)).min(MAX_OVERLOOK_CHANCE);
+    rng.gen_bool(chance)
+}
+
+/// How hard a move is to spot relative to a one-square quiet move.
+fn difficulty(board: &Board, mv: Move, gives_check: bool) -> f64 {
+    let files = (mv.from.file() as i32 - mv.to.file() as i32).abs();
+    let ranks = (mv.from.rank() as i32 - mv.to.rank() as i32).abs();
+    // long-range moves are easy to miss, especially along diagonals
+    let mut difficulty = 0.75 + 0.25 * files.max(ranks) as f64;
+    if files == ranks {
+        difficulty *= 1.25;
+    }
+
+    let color = board.side_to_move();
+    let forward = match color {
+        Color::White => mv.to.rank() > mv.from.rank(),
+        Color::Black => mv.to.rank() < mv.from.rank(),
+    };
+    if !forward && ranks != 0 {
+        difficulty *= 1.5;
+    }
+
+    // captures and checks draw attention
+    if board.colors(!color).has(mv.to) {
+        difficulty *= 0.5;
+    }
+    if gives_check {
+        difficulty *= 0.75;
+    }
+    difficulty
+}
@@ -0,0 +1,360 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use cozy_chess::{Board, Move};
+
+use crate::limits::Limits;
+use crate::options::Options;
+use crate::search::{History, Node};
+use crate::tt::TranspositionTable;
+
+/// Iterations to search for when the limits don't say when to stop.
+const DEFAULT_ITERATIONS: usize = 100;
+
+/// Lets another thread stop a running search, or tell it that the opponent played the move it
+/// was pondering on.
+pub struct SearchControl {
+    stop: AtomicBool,
+    pondering: AtomicBool,
+}
+
+impl SearchControl {
+    pub fn new(pondering: bool) -> Self {
+        SearchControl {
+            stop: AtomicBool::new(false),
+            pondering: AtomicBool::new(pondering),
+        }
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    pub fn ponderhit(&self) {
+        self.pondering.store(false, Ordering::SeqCst);
+    }
+}
+
+/// The state of a search, as passed to the callback of `Engine::search_with` after every
+/// iteration.
+pub struct SearchInfo<'a> {
+    pub board: &'a Board,
+    pub root: &'a Node,
+    pub nodes: usize,
+    pub elapsed: Duration,
+    /// The root move the last iteration went through, or `None` if it expanded the root.
+    pub currmove: Option<Move>,
+}
+
+pub struct SearchStats {
+    pub nodes: usize,
+    pub elapsed: Duration,
+}
+
+/// A badgine instance: its options, the position to search and what it has learned so far.
+pub struct Engine {
+    options: Options,
+    /// One table for each search thread, the main thread's first.
+    tts: Vec<TranspositionTable>,
+    board: Board,
+    history: History,
+    /// The tree from the last search, along with the position it was rooted at.
+    tree: Option<(Board, Node)>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::new(Options::default())
+    }
+}
+
+impl Engine {
+    pub fn new(options: Options) -> Self {
+        Engine {
+            tts: new_tables(&options),
+            options,
+            board: Board::default(),
+            history: History::default(),
+            tree: None,
+        }
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Sets a UCI option by name.
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        self.options.set(name, value)?;
+        match &*name.to_ascii_lowercase() {
+            "hash" | "threads" => self.tts = new_tables(&self.options),
+            // evals in the tables and the reused tree were made with the old settings
+            "evalfile" | "qsearchdepth" | "syzygypath" => self.new_game(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Forgets everything learned from previous searches.
+    pub fn new_game(&mut self) {
+        self.tree = None;
+        self.tts.iter_mut().for_each(TranspositionTable::clear);
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Sets the position to search to the one reached by playing `moves` from `start`.
+    pub fn set_position(&mut self, start: &Board, moves: &[Move]) -> Result<(), String> {
+        let mut board = start.clone();
+        let mut history = History::default();
+        for &mv in moves {
+            if !board.is_legal(mv) {
+                return Err(format!("illegal move '{mv}'"));
+            }
+            history.push_game(board.hash());
+            board.play_unchecked(mv);
+        }
+        self.board = board;
+        self.history = history;
+        Ok(())
+    }
+
+    /// Picks a move from the opening book, if `OwnBook` is on and the position is in the book.
+    pub fn book_move(&self) -> Option<Move> {
+        if !self.options.own_book {
+            return None;
+        }
+        let book = self.options.book.as_ref()?;
+        book.pick(&self.board, &mut self.options.rng())
+    }
+
+    /// Searches the current position until `limits` are reached. Infinite and ponder searches
+    /// never finish on their own, so they need `search_with`.
+    pub fn search(&mut self, limits: &Limits) -> SearchStats {
+        self.search_with(limits, &SearchControl::new(false), |_| {})
+    }
+
+    /// Searches the current position until `limits` are reached or `control` stops it, calling
+    /// `report` after every iteration. With more than one thread, the extra threads search trees
+    /// of their own, which are merged into the main one at the end.
+    pub fn search_with(
+        &mut self,
+        limits: &Limits,
+        control: &SearchControl,
+        mut report: impl FnMut(&SearchInfo),
+    ) -> SearchStats {
+        let search_start = Instant::now();
+        let mut start = search_start;
+        let mut was_pondering = control.pondering.load(Ordering::SeqCst);
+        let budget = limits.time_budget(self.board.side_to_move());
+        let max_iterations = self.options.iteration_cap().unwrap_or(
+            match limits.is_bounded(self.board.side_to_move()) {
+                true => usize::MAX,
+                false => DEFAULT_ITERATIONS,
+            },
+        );
+        let params = &self.options.search_params();
+        let mut rng = self.options.rng();
+
+        let board = &self.board;
+        let (tt, helper_tts) = self.tts.split_first_mut().unwrap();
+        let max_tree_size = max_tree_size(&self.options);
+        let mut root = self
+            .tree
+            .take()
+            .and_then(|(old, node)| node.into_descendant(&old, board, 2))
+            // a node below the root may have claimed a draw that the root has to play on from
+            .filter(|node| !node.children().is_empty())
+            .unwrap_or_else(|| Node::new(board, &mut self.history, params, tt));
+
+        let helpers_done = &AtomicBool::new(false);
+        let nodes = &AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            let helpers: Vec<_> = helper_tts
+                .iter_mut()
+                .enumerate()
+                .map(|(i, tt)| {
+                    let mut history = self.history.clone();
+                    let mut rng = self.options.thread_rng(i + 1);
+                    scope.spawn(move || {
+                        let mut root = Node::new(board, &mut history, params, tt);
+                        let mut tree_size = 1;
+                        while !helpers_done.load(Ordering::SeqCst)
+                            && limits
+                                .nodes
+                                .is_none_or(|n| nodes.load(Ordering::Relaxed) < n)
+                        {
+                            let (new_nodes, _) =
+                                root.search(board.clone(), &mut history, params, tt, &mut rng);
+                            nodes.fetch_add(new_nodes, Ordering::Relaxed);
+                            tree_size += new_nodes;
+                            if tree_size >= max_tree_size {
+                                tree_size -= root.prune(tree_size / 2);
+                            }
+                        }
+                        root
+                    })
+                })
+                .collect();
+
+            let mut tree_size = root.size();
+            let mut iterations = 0;
+            // always search at least once so that an immediate stop still has a move to report
+            while iterations == 0 || !control.stop.load(Ordering::SeqCst) {
+                let ponder = control.pondering.load(Ordering::SeqCst);
+                if was_pondering && !ponder {
+                    // our clock only starts running once the opponent plays the expected move
+                    start = Instant::now();
+                    was_pondering = false;
+                }
+
+                let searched = nodes.load(Ordering::Relaxed);
+                // every iteration visits at least one node, even once the whole tree is solved
+                let limit_reached = iterations > 0
+                    && (iterations >= max_iterations
+                        || (!ponder && budget.is_some_and(|b| start.elapsed() >= b))
+                        || limits
+                            .nodes
+                            .is_some_and(|n| searched >= n || iterations >= n));
+                if limit_reached {
+                    helpers_done.store(true, Ordering::SeqCst);
+                    // the UCI spec forbids sending bestmove before stop/ponderhit in these modes
+                    if ponder || limits.infinite {
+                        std::thread::sleep(Duration::from_millis(1));
+                        continue;
+                    }
+                    break;
+                }
+
+                let (new_nodes, currmove) =
+                    root.search(board.clone(), &mut self.history, params, tt, &mut rng);
+                nodes.fetch_add(new_nodes, Ordering::Relaxed);
+                iterations += 1;
+                tree_size += new_nodes;
+                if tree_size >= max_tree_size {
+                    // make room to keep searching instead of running out of memory
+                    tree_size -= root.prune(tree_size / 2);
+                }
+                report(&SearchInfo {
+                    board,
+                    root: &root,
+                    nodes: nodes.load(Ordering::Relaxed),
+                    elapsed: search_start.elapsed(),
+                    currmove,
+                });
+            }
+
+            helpers_done.store(true, Ordering::SeqCst);
+            for helper in helpers {
+                root.merge(helper.join().unwrap(), params.mode);
+            }
+        });
+
+        self.tree = Some((self.board.clone(), root));
+        SearchStats {
+            nodes: nodes.load(Ordering::Relaxed),
+            elapsed: search_start.elapsed(),
+        }
+    }
+
+    /// The search tree for the current position, if it has been searched.
+    pub fn root(&self) -> Option<&Node> {
+        let (board, root) = self.tree.as_ref()?;
+        (board == &self.board).then_some(root)
+    }
+
+    /// The best move found by the last search of the current position.
+    pub fn best_move(&self) -> Option<Move> {
+        self.root()?.children().first().map(|&(mv, _)| mv)
+    }
+}
+
+/// Number of nodes each search tree may grow to within its share of `Hash`.
+fn max_tree_size(options: &Options) -> usize {
+    // leave as much again for allocator overhead and spare capacity in the lists of children
+    thread_memory(options) / (2 * std::mem::size_of::<(Move, Node)>())
+}
+
+/// Creates a transposition table for each search thread.
+fn new_tables(options: &Options) -> Vec<TranspositionTable> {
+    (0..options.threads)
+        .map(|_| TranspositionTable::new(thread_memory(options)))
+        .collect()
+}
+
+/// Bytes of `Hash` for each of a search thread's transposition table and tree, which get half
+/// of the thread's share each.
+fn thread_memory(options: &Options) -> usize {
+    options.hash * 1024 * 1024 / options.threads / 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An engine with `options` set on top of `Seed` and `Iterations`, after searching the
+    /// position reached by `moves`.
+    fn searched(options: &[(&str, &str)], moves: &str) -> Engine {
+        let mut engine = Engine::default();
+        engine.set_option("Seed", "7").unwrap();
+        engine.set_option("Iterations", "300").unwrap();
+        for (name, value) in options {
+            engine.set_option(name, value).unwrap();
+        }
+        search(&mut engine, moves);
+        engine
+    }
+
+    fn search(engine: &mut Engine, moves: &str) {
+        let moves: Vec<Move> = moves
+            .split_whitespace()
+            .map(|m| m.parse().unwrap())
+            .collect();
+        engine.set_position(&Board::default(), &moves).unwrap();
+        engine.search(&Limits::default());
+    }
+
+    fn pv(engine: &Engine) -> Vec<Move> {
+        let mut pv = vec![];
+        engine.root().unwrap().get_pv(&mut pv);
+        pv
+    }
+
+    #[test]
+    fn same_seed_gives_same_search() {
+        let moves = "e2e4 e7e5 g1f3 b8c6 f1b5";
+        let a = searched(&[], moves);
+        let b = searched(&[], moves);
+        assert!(a.best_move().is_some());
+        assert_eq!(a.best_move(), b.best_move());
+        assert_eq!(pv(&a), pv(&b));
+    }
+
+    #[test]
+    fn same_seed_gives_same_single_threaded_mcts_search() {
+        let options = [
+            ("Threads", "1"),
+            ("SearchMode", "MCTS"),
+            ("Iterations", "100"),
+        ];
+        let moves = "d2d4 d7d5 c2c4";
+        let a = searched(&options, moves);
+        let b = searched(&options, moves);
+        assert!(a.best_move().is_some());
+        assert_eq!(a.best_move(), b.best_move());
+        assert_eq!(pv(&a), pv(&b));
+    }
+
+    #[test]
+    fn new_game_gives_same_search_as_new_engine() {
+        let moves = "e2e4 c7c5";
+        let mut a = searched(&[], "e2e4");
+        a.new_game();
+        search(&mut a, moves);
+        let b = searched(&[], moves);
+        assert_eq!(a.best_move(), b.best_move());
+        assert_eq!(pv(&a), pv(&b));
+    }
+}
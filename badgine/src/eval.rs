@@ -1,26 +1,101 @@
 use std::collections::VecDeque;
 
 use cozy_chess::{get_king_moves, BitBoard, Board, Color, Piece};
+use serde::{Deserialize, Serialize};
 
-pub fn evaluate(board: &Board) -> i32 {
-    material(board) + king_space(board, board.side_to_move())
-        - king_space(board, !board.side_to_move())
-        + pawn_advancedness(board, board.side_to_move())
-        - pawn_advancedness(board, !board.side_to_move())
+const MAX_PHASE: i32 = 24;
+
+/// Piece-square tables for each piece, laid out as seen from white's side of the board, so the
+/// 8th rank comes first.
+pub type Psqt = [[[i32; 8]; 8]; Piece::NUM];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Weights {
+    pub pawn: i32,
+    pub knight: i32,
+    pub bishop: i32,
+    pub rook: i32,
+    pub queen: i32,
+    pub king_space: i32,
+    pub pawn_ranks: [i32; 8],
+    pub mg_psqt: Psqt,
+    pub eg_psqt: Psqt,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            pawn: 100,
+            knight: 300,
+            bishop: 300,
+            rook: 500,
+            queen: 900,
+            king_space: 1,
+            pawn_ranks: [0, 0, 4, 10, 15, 19, 25, 0],
+            mg_psqt: MG_PSQT,
+            eg_psqt: EG_PSQT,
+        }
+    }
+}
+
+impl Weights {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        toml::from_str(&contents).map_err(|e| match e.span() {
+            Some(span) => {
+                let line = contents[..span.start].matches('\n').count() + 1;
+                format!("line {line}: {}", e.message())
+            }
+            None => e.message().to_owned(),
+        })
+    }
+}
+
+pub fn evaluate(board: &Board, weights: &Weights) -> i32 {
+    let stm = board.side_to_move();
+    let phase = phase(board);
+    let mg = psqt(board, stm, &weights.mg_psqt) - psqt(board, !stm, &weights.mg_psqt);
+    let eg = psqt(board, stm, &weights.eg_psqt) - psqt(board, !stm, &weights.eg_psqt)
+        + weights.king_space * (king_space(board, stm) - king_space(board, !stm))
+        + pawn_advancedness(board, stm, weights)
+        - pawn_advancedness(board, !stm, weights);
+    material(board, weights) + (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE
 }
 
-fn material(board: &Board) -> i32 {
-    let pawns = board.pieces(Piece::Pawn);
+/// Game phase from remaining non-pawn material, from `MAX_PHASE` at the start to 0 with only
+/// kings and pawns left.
+fn phase(board: &Board) -> i32 {
     let minors = board.pieces(Piece::Knight) | board.pieces(Piece::Bishop);
-    let rooks = board.pieces(Piece::Rook);
-    let queens = board.pieces(Piece::Queen);
+    let phase = minors.popcnt()
+        + 2 * board.pieces(Piece::Rook).popcnt()
+        + 4 * board.pieces(Piece::Queen).popcnt();
+    (phase as i32).min(MAX_PHASE)
+}
+
+fn psqt(board: &Board, color: Color, tables: &Psqt) -> i32 {
+    let mut score = 0;
+    for &piece in &Piece::ALL {
+        for sq in board.pieces(piece) & board.colors(color) {
+            let row = 7 - sq.rank().relative_to(color) as usize;
+            score += tables[piece as usize][row][sq.file() as usize];
+        }
+    }
+    score
+}
+
+fn material(board: &Board, weights: &Weights) -> i32 {
     let ours = board.colors(board.side_to_move());
     let theirs = !ours;
-    let pawns = (ours & pawns).popcnt() as i32 - (pawns & theirs).popcnt() as i32;
-    let minors = (ours & minors).popcnt() as i32 - (minors & theirs).popcnt() as i32;
-    let rooks = (ours & rooks).popcnt() as i32 - (rooks & theirs).popcnt() as i32;
-    let queens = (ours & queens).popcnt() as i32 - (queens & theirs).popcnt() as i32;
-    100 * pawns + 300 * minors + 500 * rooks + 900 * queens
+    let count = |piece| {
+        let pieces = board.pieces(piece);
+        (ours & pieces).popcnt() as i32 - (pieces & theirs).popcnt() as i32
+    };
+    weights.pawn * count(Piece::Pawn)
+        + weights.knight * count(Piece::Knight)
+        + weights.bishop * count(Piece::Bishop)
+        + weights.rook * count(Piece::Rook)
+        + weights.queen * count(Piece::Queen)
 }
 
 fn king_space(board: &Board, color: Color) -> i32 {
@@ -60,11 +135,100 @@ fn get_attack_set(board: &Board, color: Color) -> BitBoard {
     attacks
 }
 
-fn pawn_advancedness(board: &Board, color: Color) -> i32 {
-    const RANK_SCORES: [i32; 8] = [0, 0, 4, 10, 15, 19, 25, 0];
+fn pawn_advancedness(board: &Board, color: Color, weights: &Weights) -> i32 {
     let mut score = 0;
-    for sq in board.pieces(Piece::Pawn) | board.colors(color) {
-        score += RANK_SCORES[sq.rank().relative_to(color) as usize];
+    for sq in board.pieces(Piece::Pawn) & board.colors(color) {
+        score += weights.pawn_ranks[sq.rank().relative_to(color) as usize];
     }
     score
 }
+
+#[rustfmt::skip]
+const MG_PSQT: Psqt = [
+    // pawn
+    [
+        [  0,   0,   0,   0,   0,   0,   0,   0],
+        [ 10,  10,  10,  10,  10,  10,  10,  10],
+        [  0,   0,   5,  10,  10,   5,   0,   0],
+        [  0,   0,   5,  20,  20,   5,   0,   0],
+        [  0,   0,   0,  20,  20,   0,   0,   0],
+        [  5,  -5, -10,   0,   0, -10,  -5,   5],
+        [  5,  10,  10, -20, -20,  10,  10,   5],
+        [  0,   0,   0,   0,   0,   0,   0,   0],
+    ],
+    // knight
+    [
+        [-50, -40, -30, -30, -30, -30, -40, -50],
+        [-40, -20,   0,   0,   0,   0, -20, -40],
+        [-30,   0,  10,  15,  15,  10,   0, -30],
+        [-30,   5,  15,  20,  20,  15,   5, -30],
+        [-30,   0,  15,  20,  20,  15,   0, -30],
+        [-30,   5,  10,  15,  15,  10,   5, -30],
+        [-40, -20,   0,   5,   5,   0, -20, -40],
+        [-50, -40, -30, -30, -30, -30, -40, -50],
+    ],
+    // bishop
+    [
+        [-20, -10, -10, -10, -10, -10, -10, -20],
+        [-10,   0,   0,   0,   0,   0,   0, -10],
+        [-10,   0,   5,  10,  10,   5,   0, -10],
+        [-10,   5,   5,  10,  10,   5,   5, -10],
+        [-10,   0,  10,  10,  10,  10,   0, -10],
+        [-10,  10,  10,  10,  10,  10,  10, -10],
+        [-10,   5,   0,   0,   0,   0,   5, -10],
+        [-20, -10, -10, -10, -10, -10, -10, -20],
+    ],
+    // rook
+    [
+        [  0,   0,   0,   0,   0,   0,   0,   0],
+        [  5,  10,  10,  10,  10,  10,  10,   5],
+        [ -5,   0,   0,   0,   0,   0,   0,  -5],
+        [ -5,   0,   0,   0,   0,   0,   0,  -5],
+        [ -5,   0,   0,   0,   0,   0,   0,  -5],
+        [ -5,   0,   0,   0,   0,   0,   0,  -5],
+        [ -5,   0,   0,   0,   0,   0,   0,  -5],
+        [  0,   0,   0,   5,   5,   0,   0,   0],
+    ],
+    // queen: mostly a penalty for wandering off before development is done
+    [
+        [-20, -20, -20, -20, -20, -20, -20, -20],
+        [-20, -20, -20, -20, -20, -20, -20, -20],
+        [-20, -15, -15, -15, -15, -15, -15, -20],
+        [-15, -10, -10, -10, -10, -10, -10, -15],
+        [-10,  -5,  -5,  -5,  -5,  -5,  -5, -10],
+        [-10,   0,   5,   5,   5,   5,   0, -10],
+        [-10,   0,   5,   0,   0,   0,   0, -10],
+        [-20, -10, -10,   0,  -5, -10, -10, -20],
+    ],
+    // king
+    [
+        [-30, -40, -40, -50, -50, -40, -40, -30],
+        [-30, -40, -40, -50, -50, -40, -40, -30],
+        [-30, -40, -40, -50, -50, -40, -40, -30],
+        [-30, -40, -40, -50, -50, -40, -40, -30],
+        [-20, -30, -30, -40, -40, -30, -30, -20],
+        [-10, -20, -20, -20, -20, -20, -20, -10],
+        [ 20,  20,   0,   0,   0,   0,  20,  20],
+        [ 20,  30,  10,   0,   0,  10,  30,  20],
+    ],
+];
+
+#[rustfmt::skip]
+const EG_PSQT: Psqt = [
+    [[0; 8]; 8],
+    [[0; 8]; 8],
+    [[0; 8]; 8],
+    [[0; 8]; 8],
+    [[0; 8]; 8],
+    // king
+    [
+        [-50, -40, -30, -20, -20, -30, -40, -50],
+        [-30, -20, -10,   0,   0, -10, -20, -30],
+        [-30, -10,  20,  30,  30,  20, -10, -30],
+        [-30, -10,  30,  40,  40,  30, -10, -30],
+        [-30, -10,  30,  40,  40,  30, -10, -30],
+        [-30, -10,  20,  30,  30,  20, -10, -30],
+        [-30, -30,   0,   0,   0,   0, -30, -30],
+        [-50, -30, -30, -30, -30, -30, -30, -50],
+    ],
+];
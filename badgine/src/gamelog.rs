@@ -0,0 +1,87 @@
+use badgine::pgn;
+use cozy_chess::{Board, Color, Move};
+
+/// Keeps a PGN record of the games played, annotated with what the engine thought of its moves.
+pub struct GameLog {
+    path: String,
+    /// Earlier games from this session, already formatted.
+    finished: String,
+    start: Board,
+    moves: Vec<(Move, Option<String>)>,
+    /// Number of moves in the position last sent by the GUI.
+    position_len: usize,
+}
+
+impl GameLog {
+    pub fn new(path: String) -> Self {
+        GameLog {
+            path,
+            finished: String::new(),
+            start: Board::default(),
+            moves: vec![],
+            position_len: 0,
+        }
+    }
+
+    pub fn new_game(&mut self) {
+        if !self.moves.is_empty() {
+            self.finished += &self.format_game();
+            self.finished.push('\n');
+        }
+        self.moves.clear();
+        self.position_len = 0;
+    }
+
+    /// Updates the game to the position sent by the GUI. Comments on moves that were already
+    /// part of the game are kept.
+    pub fn set_position(&mut self, start: &Board, moves: &[Move]) {
+        if start != &self.start {
+            self.new_game();
+            self.start = start.clone();
+        }
+        let common = self
+            .moves
+            .iter()
+            .zip(moves)
+            .take_while(|((a, _), b)| a == *b)
+            .count();
+        self.moves.truncate(common);
+        self.moves
+            .extend(moves[common..].iter().map(|&mv| (mv, None)));
+        self.position_len = moves.len();
+    }
+
+    /// Records the move the engine played in the current position and writes out the log.
+    pub fn record(&mut self, mv: Move, comment: String) -> std::io::Result<()> {
+        // replaces the result of any earlier search of the same position
+        self.moves.truncate(self.position_len);
+        self.moves.push((mv, Some(comment)));
+        std::fs::write(&self.path, self.finished.clone() + &self.format_game())
+    }
+
+    fn format_game(&self) -> String {
+        // the engine is whichever side has commented moves
+        let mut board = self.start.clone();
+        let mut engine_colors = [false; Color::NUM];
+        for (mv, comment) in &self.moves {
+            engine_colors[board.side_to_move() as usize] |= comment.is_some();
+            board.play_unchecked(*mv);
+        }
+        let player = |color: Color| match engine_colors[color as usize] {
+            true => "badgine",
+            false => "?",
+        };
+
+        let mut game = String::new();
+        game += "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n";
+        game += &format!("[White \"{}\"]\n", player(Color::White));
+        game += &format!("[Black \"{}\"]\n", player(Color::Black));
+        game += "[Result \"*\"]\n";
+        if self.start != Board::default() {
+            game += &format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", self.start);
+        }
+        game.push('\n');
+        game += &pgn::movetext(&self.start, &self.moves, "*");
+        game
+    }
+}
@@ -0,0 +1,20 @@
+//! A chess engine designed to play poorly, as a library. `Engine` is the easiest way in; the
+//! modules expose the pieces it is built from.
+
+mod blunder;
+pub mod book;
+mod engine;
+pub mod eval;
+pub mod limits;
+pub mod options;
+pub mod pgn;
+pub mod search;
+pub mod tablebase;
+pub mod tt;
+pub mod uci;
+
+pub use engine::{Engine, SearchControl, SearchInfo, SearchStats};
+pub use eval::{evaluate, Weights};
+pub use limits::Limits;
+pub use options::Options;
+pub use search::{Eval, Node};
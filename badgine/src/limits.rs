@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use cozy_chess::Color;
+
+const MOVE_OVERHEAD: Duration = Duration::from_millis(30);
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+#[derive(Default, Debug, Clone)]
+pub struct Limits {
+    pub wtime: Option<Duration>,
+    pub btime: Option<Duration>,
+    pub winc: Option<Duration>,
+    pub binc: Option<Duration>,
+    pub movestogo: Option<u32>,
+    pub movetime: Option<Duration>,
+    pub nodes: Option<usize>,
+    pub infinite: bool,
+    pub ponder: bool,
+}
+
+impl Limits {
+    pub fn parse<'a>(mut params: impl Iterator<Item = &'a str>) -> Self {
+        let mut limits = Limits::default();
+        while let Some(param) = params.next() {
+            match param {
+                "wtime" => limits.wtime = parse_millis(params.next()),
+                "btime" => limits.btime = parse_millis(params.next()),
+                "winc" => limits.winc = parse_millis(params.next()),
+                "binc" => limits.binc = parse_millis(params.next()),
+                "movetime" => limits.movetime = parse_millis(params.next()),
+                "movestogo" => {
+                    limits.movestogo = parse_count(params.next()).map(|v| v.max(1) as u32)
+                }
+                "nodes" => limits.nodes = parse_count(params.next()),
+                "infinite" => limits.infinite = true,
+                "ponder" => limits.ponder = true,
+                _ => {}
+            }
+        }
+        limits
+    }
+
+    /// Whether the search has anything to stop it other than the default iteration count. Only the
+    /// clock of `stm`, the side to move, counts.
+    pub fn is_bounded(&self, stm: Color) -> bool {
+        self.infinite || self.nodes.is_some() || self.time_budget(stm).is_some()
+    }
+
+    /// How long to think for, given the side to move. `None` means no time limit.
+    pub fn time_budget(&self, stm: Color) -> Option<Duration> {
+        if self.infinite {
+            return None;
+        }
+        if let Some(movetime) = self.movetime {
+            return Some(movetime.saturating_sub(MOVE_OVERHEAD));
+        }
+
+        let (time, inc) = match stm {
+            Color::White => (self.wtime?, self.winc.unwrap_or_default()),
+            Color::Black => (self.btime?, self.binc.unwrap_or_default()),
+        };
+        let available = time.saturating_sub(MOVE_OVERHEAD);
+        let moves_to_go = self.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO);
+        let budget = time / moves_to_go + inc * 3 / 4;
+        Some(budget.min(available))
+    }
+}
+
+fn parse_millis(value: Option<&str>) -> Option<Duration> {
+    // GUIs occasionally send negative times when a clock has run out
+    let millis = value?.parse::<i64>().ok()?;
+    Some(Duration::from_millis(millis.max(0) as u64))
+}
+
+fn parse_count(value: Option<&str>) -> Option<usize> {
+    value?.parse().ok()
+}
@@ -1,12 +1,65 @@
-use cozy_chess::{Board, File, Move, Piece, Square};
-use nohash::{IntMap, IntSet};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-use crate::search::Node;
+use badgine::uci::{from_uci_castling, to_uci_castling};
+use badgine::{Engine, Limits, Node, Options, SearchControl};
+use cozy_chess::{Board, Color, File, Move, Piece, Rank};
 
-mod search;
-mod eval;
+use crate::gamelog::GameLog;
+
+mod bench;
+mod gamelog;
+
+const INFO_INTERVAL: Duration = Duration::from_millis(500);
+
+struct SearchThread {
+    handle: JoinHandle<()>,
+    control: Arc<SearchControl>,
+}
 
 fn main() {
+    let mut args = vec![];
+    let mut seed = None;
+    let mut cli = std::env::args().skip(1);
+    while let Some(arg) = cli.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let value = cli.next().unwrap_or_default();
+                match value.parse::<u64>() {
+                    Ok(s) if s <= badgine::options::MAX_SEED => seed = Some(s),
+                    _ => {
+                        eprintln!("invalid seed '{value}'");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            _ => args.push(arg),
+        }
+    }
+    match args.first().map(String::as_str) {
+        Some("bench") => return bench::bench(seed.unwrap_or(bench::DEFAULT_SEED)),
+        Some("epd") => {
+            let Some(path) = args.get(1) else {
+                eprintln!("usage: badgine epd <file> [iterations]");
+                std::process::exit(1);
+            };
+            let iterations = args.get(2).map(|n| {
+                n.parse().unwrap_or_else(|_| {
+                    eprintln!("invalid iteration count '{n}'");
+                    std::process::exit(1);
+                })
+            });
+            if let Err(e) = bench::epd(path, iterations, seed.unwrap_or(bench::DEFAULT_SEED)) {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        _ => {}
+    }
+
     let mut buf = String::new();
     let stdin = std::io::stdin();
     stdin.read_line(&mut buf).unwrap();
@@ -14,16 +67,27 @@ fn main() {
         eprintln!("Expected first command to be 'uci'");
         std::process::exit(1);
     }
+    let options = Options {
+        seed: seed.unwrap_or(0),
+        ..Default::default()
+    };
     println!("id name badgine 0.1.0");
     println!("id author MinusKelvin");
+    for line in options.uci_options() {
+        println!("{line}");
+    }
     println!("uciok");
 
-    let mut board = Board::default();
-    let mut history = IntSet::default();
+    let engine = Arc::new(Mutex::new(Engine::new(options)));
+    let mut search = None;
+    let log = Arc::new(Mutex::new(None::<GameLog>));
 
     loop {
         buf.clear();
-        stdin.read_line(&mut buf).unwrap();
+        if stdin.read_line(&mut buf).unwrap() == 0 {
+            finish_search(&mut search);
+            return;
+        }
         if buf.trim().is_empty() {
             continue;
         }
@@ -33,46 +97,71 @@ fn main() {
                 println!("readyok");
             }
             "position" => {
-                let position = params.next().unwrap();
-                board = match position {
-                    "startpos" => cozy_chess::Board::default(),
-                    _ => panic!("fen position unsupported"),
-                };
-                let mut history_counts = IntMap::<_, i32>::default();
-                if params.next() == Some("moves") {
-                    for mv in params.map(|m| m.parse::<Move>().unwrap()) {
-                        *history_counts.entry(board.hash()).or_default() += 1;
-                        let mv = from_uci_castling(&board, mv);
-                        board.play(mv);
+                finish_search(&mut search);
+                let mut engine = engine.lock().unwrap();
+                let result =
+                    parse_position(params, engine.options().chess960).and_then(|(start, moves)| {
+                        engine.set_position(&start, &moves)?;
+                        Ok((start, moves))
+                    });
+                match result {
+                    Ok((start, moves)) => {
+                        if let Some(log) = log.lock().unwrap().as_mut() {
+                            log.set_position(&start, &moves);
+                        }
                     }
+                    Err(e) => println!("info string {e}"),
+                }
+            }
+            "setoption" => {
+                finish_search(&mut search);
+                let mut engine = engine.lock().unwrap();
+                let (name, value) = parse_setoption(params);
+                if let Err(e) = engine.set_option(&name, &value) {
+                    println!("info string {e}");
+                } else if name.eq_ignore_ascii_case("logfile") {
+                    *log.lock().unwrap() = engine.options().log_file.clone().map(GameLog::new);
+                }
+            }
+            "ucinewgame" => {
+                finish_search(&mut search);
+                engine.lock().unwrap().new_game();
+                if let Some(log) = log.lock().unwrap().as_mut() {
+                    log.new_game();
                 }
-                history = history_counts
-                    .into_iter()
-                    .filter(|&(_, c)| c > 1)
-                    .map(|(h, _)| h)
-                    .collect();
             }
             "go" => {
-                let mut root = Node::new(&board, &mut history);
-
-                let mut nodes = 0;
-                for _ in 0..100 {
-                    nodes += root.search(board.clone(), &mut history);
+                finish_search(&mut search);
+                let limits = Limits::parse(params);
+                // infinite and ponder searches must wait for stop, so don't bother with the book
+                if !limits.infinite && !limits.ponder {
+                    let engine = engine.lock().unwrap();
+                    if let Some(mv) = engine.book_move() {
+                        let chess960 = engine.options().chess960;
+                        println!("bestmove {}", to_uci_castling(engine.board(), mv, chess960));
+                        log_move(&log, mv, "book".to_owned());
+                        continue;
+                    }
                 }
-
-                let mut pv = vec![];
-                root.get_pv(&mut pv);
-                print!("info score {} depth {} nodes {nodes} pv", root.eval(), root.depth());
-                let mut b = board.clone();
-                for &mv in &pv {
-                    print!(" {}", to_uci_castling(&b, mv));
-                    b.play_unchecked(mv);
+                let control = Arc::new(SearchControl::new(limits.ponder));
+                let handle = std::thread::spawn({
+                    let engine = engine.clone();
+                    let control = control.clone();
+                    let log = log.clone();
+                    move || run_search(&mut engine.lock().unwrap(), &limits, &control, &log)
+                });
+                search = Some(SearchThread { handle, control });
+            }
+            "stop" => {
+                finish_search(&mut search);
+            }
+            "ponderhit" => {
+                if let Some(search) = &search {
+                    search.control.ponderhit();
                 }
-                println!();
-                
-                println!("bestmove {}", pv[0]);
             }
             "quit" => {
+                finish_search(&mut search);
                 std::process::exit(0);
             }
             _ => {}
@@ -80,24 +169,221 @@ fn main() {
     }
 }
 
-fn to_uci_castling(board: &Board, mut mv: Move) -> Move {
-    if board.color_on(mv.from) == board.color_on(mv.to) {
-        if mv.to.file() > mv.from.file() {
-            mv.to = Square::new(File::G, mv.to.rank());
-        } else {
-            mv.to = Square::new(File::C, mv.to.rank());
+fn finish_search(search: &mut Option<SearchThread>) {
+    if let Some(search) = search.take() {
+        search.control.stop();
+        search.handle.join().unwrap();
+    }
+}
+
+/// Searches the engine's position, reporting progress and the best move over UCI.
+fn run_search(
+    engine: &mut Engine,
+    limits: &Limits,
+    control: &SearchControl,
+    log: &Mutex<Option<GameLog>>,
+) {
+    let multipv = engine.options().multipv;
+    let chess960 = engine.options().chess960;
+    let mut last_info = Instant::now();
+    let stats = engine.search_with(limits, control, |info| {
+        if last_info.elapsed() < INFO_INTERVAL {
+            return;
         }
+        last_info = Instant::now();
+        print_info(
+            info.board,
+            info.root,
+            info.nodes,
+            info.elapsed,
+            multipv,
+            chess960,
+        );
+        if let Some(mv) = info.currmove {
+            let number = info
+                .root
+                .children()
+                .iter()
+                .position(|&(m, _)| m == mv)
+                .unwrap()
+                + 1;
+            println!(
+                "info currmove {} currmovenumber {number}",
+                to_uci_castling(info.board, mv, chess960)
+            );
+        }
+    });
+
+    let board = engine.board();
+    let root = engine.root().unwrap();
+    print_info(board, root, stats.nodes, stats.elapsed, multipv, chess960);
+
+    let mut pv = vec![];
+    root.get_pv(&mut pv);
+    match pv.first() {
+        Some(&mv) => {
+            let mut bestmove = format!("bestmove {}", to_uci_castling(board, mv, chess960));
+            if let Some(&reply) = pv.get(1) {
+                let mut b = board.clone();
+                b.play_unchecked(mv);
+                write!(bestmove, " ponder {}", to_uci_castling(&b, reply, chess960)).unwrap();
+            }
+            println!("{bestmove}");
+            let comment = format!(
+                "{}, depth {}, nodes {}, pv {}",
+                root.eval(),
+                root.depth(),
+                stats.nodes,
+                badgine::pgn::san_line(board, &pv)
+            );
+            log_move(log, mv, comment);
+        }
+        None => println!("bestmove 0000"),
+    }
+}
+
+fn log_move(log: &Mutex<Option<GameLog>>, mv: Move, comment: String) {
+    if let Some(log) = log.lock().unwrap().as_mut() {
+        if let Err(e) = log.record(mv, comment) {
+            println!("info string failed to write log file: {e}");
+        }
+    }
+}
+
+fn print_info(
+    board: &Board,
+    root: &Node,
+    nodes: usize,
+    elapsed: Duration,
+    multipv: usize,
+    chess960: bool,
+) {
+    let time = elapsed.as_millis();
+    let nps = (nodes as f64 / elapsed.as_secs_f64().max(0.001)) as u64;
+    let stats = format!("nodes {nodes} nps {nps} time {time}");
+
+    let children = root.children();
+    if children.is_empty() {
+        println!("info depth 0 score {} {stats}", root.eval());
+    }
+    for (i, (mv, child)) in children.iter().take(multipv).enumerate() {
+        let mut line = vec![*mv];
+        child.get_pv(&mut line);
+        // the pv can end in a node that was expanded but never searched below, at depth 0
+        let seldepth = line.len().max(child.depth() + 1);
+        println!(
+            "info multipv {} depth {} seldepth {seldepth} score {} {stats} pv{}",
+            i + 1,
+            line.len(),
+            -child.eval().count_time(),
+            format_pv(board, &line, chess960)
+        );
+    }
+}
+
+fn format_pv(board: &Board, pv: &[Move], chess960: bool) -> String {
+    let mut board = board.clone();
+    let mut result = String::new();
+    for &mv in pv {
+        write!(result, " {}", to_uci_castling(&board, mv, chess960)).unwrap();
+        board.play_unchecked(mv);
     }
-    mv
+    result
 }
 
-fn from_uci_castling(board: &Board, mut mv: Move) -> Move {
-    if mv.from.file() == File::E && board.piece_on(mv.from) == Some(Piece::King) {
-        if mv.to.file() == File::G {
-            mv.to = Square::new(File::H, mv.to.rank());
-        } else if mv.to.file() == File::C {
-            mv.to = Square::new(File::A, mv.to.rank());
+fn parse_setoption<'a>(mut params: impl Iterator<Item = &'a str>) -> (String, String) {
+    if params.next() != Some("name") {
+        return (String::new(), String::new());
+    }
+    let name: Vec<_> = params.by_ref().take_while(|&p| p != "value").collect();
+    let value: Vec<_> = params.collect();
+    (name.join(" "), value.join(" "))
+}
+
+fn parse_position<'a>(
+    mut params: impl Iterator<Item = &'a str>,
+    chess960: bool,
+) -> Result<(Board, Vec<Move>), String> {
+    let start = match params.next() {
+        Some("startpos") => {
+            match params.next() {
+                None | Some("moves") => {}
+                Some(other) => return Err(format!("expected 'moves', found '{other}'")),
+            }
+            Board::default()
+        }
+        Some("fen") => {
+            let mut fen: Vec<_> = params.by_ref().take_while(|&p| p != "moves").collect();
+            if fen.len() == 4 {
+                // EPD-style FEN without move counters
+                fen.extend(["0", "1"]);
+            }
+            let fen = fen.join(" ");
+            parse_fen(&fen, chess960)?
+        }
+        Some(other) => return Err(format!("unknown position type '{other}'")),
+        None => return Err("missing position type".to_owned()),
+    };
+
+    let mut board = start.clone();
+    let mut moves = vec![];
+    for mv in params {
+        let parsed = mv
+            .parse::<Move>()
+            .map_err(|_| format!("invalid move '{mv}'"))?;
+        let parsed = from_uci_castling(&board, parsed, chess960);
+        if !board.is_legal(parsed) {
+            return Err(format!("illegal move '{mv}'"));
         }
+        board.play_unchecked(parsed);
+        moves.push(parsed);
+    }
+
+    Ok((start, moves))
+}
+
+fn parse_fen(fen: &str, chess960: bool) -> Result<Board, String> {
+    let invalid = |e: &dyn std::fmt::Debug| format!("invalid fen '{fen}': {e:?}");
+    if !chess960 {
+        return Board::from_fen(fen, false).map_err(|e| invalid(&e));
+    }
+
+    // Translate X-FEN castling rights, where K and Q refer to the outermost rook on that side, to
+    // Shredder-FEN, which always names the rook's file.
+    let mut fields: Vec<_> = fen.split(' ').collect();
+    let castling = std::mem::replace(
+        fields
+            .get_mut(2)
+            .ok_or_else(|| invalid(&"missing castling rights"))?,
+        "-",
+    );
+    let board = Board::from_fen(&fields.join(" "), false).map_err(|e| invalid(&e))?;
+    let mut rights = String::new();
+    for c in castling.chars().filter(|&c| c != '-') {
+        let color = match c.is_ascii_uppercase() {
+            true => Color::White,
+            false => Color::Black,
+        };
+        let king = board.king(color).file();
+        let rooks = (board.pieces(Piece::Rook)
+            & board.colors(color)
+            & Rank::First.relative_to(color).bitboard())
+        .into_iter()
+        .map(|sq| sq.file());
+        let file = match c.to_ascii_lowercase() {
+            'k' => rooks.filter(|&f| f > king).max(),
+            'q' => rooks.filter(|&f| f < king).min(),
+            c => File::try_from(c).ok(),
+        };
+        let file = char::from(file.ok_or_else(|| invalid(&"bad castling rights"))?);
+        rights.push(match color {
+            Color::White => file.to_ascii_uppercase(),
+            Color::Black => file,
+        });
+    }
+    if rights.is_empty() {
+        rights.push('-');
     }
-    mv
+    fields[2] = &rights;
+    Board::from_fen(&fields.join(" "), true).map_err(|e| invalid(&e))
 }
@@ -0,0 +1,225 @@
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::book::Book;
+use crate::eval::Weights;
+use crate::search::{SearchMode, SearchParams};
+use crate::tablebase::Tablebase;
+
+const MIN_ELO: i64 = 400;
+const MAX_ELO: i64 = 2000;
+pub const MAX_SEED: u64 = 2147483647;
+
+pub struct Options {
+    pub search_mode: SearchMode,
+    pub limit_strength: bool,
+    pub elo: i64,
+    pub exploration: i64,
+    pub iterations: usize,
+    pub blunders: i64,
+    pub hash: usize,
+    pub threads: usize,
+    pub qsearch_depth: usize,
+    pub weights: Weights,
+    pub multipv: usize,
+    pub chess960: bool,
+    pub own_book: bool,
+    pub book: Option<Book>,
+    pub tablebase: Option<Arc<Tablebase>>,
+    pub log_file: Option<String>,
+    pub seed: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            search_mode: SearchMode::Random,
+            limit_strength: false,
+            elo: 1200,
+            exploration: 100,
+            iterations: 0,
+            blunders: 0,
+            hash: 16,
+            threads: 1,
+            qsearch_depth: 0,
+            weights: Weights::default(),
+            multipv: 1,
+            chess960: false,
+            own_book: false,
+            book: None,
+            tablebase: None,
+            log_file: None,
+            seed: 0,
+        }
+    }
+}
+
+impl Options {
+    /// The `option` lines to send in reply to `uci`.
+    pub fn uci_options(&self) -> Vec<String> {
+        vec![
+            "option name SearchMode type combo default Random var Random var MCTS".to_owned(),
+            "option name UCI_LimitStrength type check default false".to_owned(),
+            format!("option name UCI_Elo type spin default 1200 min {MIN_ELO} max {MAX_ELO}"),
+            "option name Exploration type spin default 100 min 1 max 1000".to_owned(),
+            "option name Iterations type spin default 0 min 0 max 100000000".to_owned(),
+            "option name Blunders type spin default 0 min 0 max 100".to_owned(),
+            "option name Hash type spin default 16 min 1 max 65536".to_owned(),
+            "option name Threads type spin default 1 min 1 max 256".to_owned(),
+            "option name QSearchDepth type spin default 0 min 0 max 32".to_owned(),
+            "option name EvalFile type string default <empty>".to_owned(),
+            "option name MultiPV type spin default 1 min 1 max 256".to_owned(),
+            "option name UCI_Chess960 type check default false".to_owned(),
+            "option name OwnBook type check default false".to_owned(),
+            "option name BookFile type string default <empty>".to_owned(),
+            "option name SyzygyPath type string default <empty>".to_owned(),
+            "option name LogFile type string default <empty>".to_owned(),
+            format!(
+                "option name Seed type spin default {} min 0 max {MAX_SEED}",
+                self.seed
+            ),
+        ]
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match &*name.to_ascii_lowercase() {
+            "searchmode" => {
+                self.search_mode = match &*value.to_ascii_lowercase() {
+                    "random" => SearchMode::Random,
+                    "mcts" => SearchMode::Mcts,
+                    _ => return Err(format!("expected 'Random' or 'MCTS', found '{value}'")),
+                }
+            }
+            "uci_limitstrength" => self.limit_strength = parse_check(value)?,
+            "uci_elo" => self.elo = parse_spin(value, MIN_ELO, MAX_ELO)?,
+            "exploration" => self.exploration = parse_spin(value, 1, 1000)?,
+            "iterations" => self.iterations = parse_spin(value, 0, 100_000_000)? as usize,
+            "blunders" => self.blunders = parse_spin(value, 0, 100)?,
+            "hash" => self.hash = parse_spin(value, 1, 65536)? as usize,
+            "threads" => self.threads = parse_spin(value, 1, 256)? as usize,
+            "qsearchdepth" => self.qsearch_depth = parse_spin(value, 0, 32)? as usize,
+            "multipv" => self.multipv = parse_spin(value, 1, 256)? as usize,
+            "uci_chess960" => self.chess960 = parse_check(value)?,
+            "ownbook" => self.own_book = parse_check(value)?,
+            "evalfile" => {
+                self.weights = match value {
+                    "" | "<empty>" => Weights::default(),
+                    path => Weights::load(path)
+                        .map_err(|e| format!("failed to load eval file '{path}': {e}"))?,
+                }
+            }
+            "bookfile" => {
+                self.book = match value {
+                    "" | "<empty>" => None,
+                    path => Some(
+                        Book::load(path)
+                            .map_err(|e| format!("failed to load book file '{path}': {e}"))?,
+                    ),
+                }
+            }
+            "syzygypath" => {
+                self.tablebase = match value {
+                    "" | "<empty>" => None,
+                    path => Some(Arc::new(Tablebase::load(path).map_err(|e| {
+                        format!("failed to load tablebases from '{path}': {e}")
+                    })?)),
+                }
+            }
+            "seed" => self.seed = parse_spin(value, 0, MAX_SEED as i64)? as u64,
+            "logfile" => {
+                self.log_file = match value {
+                    "" | "<empty>" => None,
+                    path => Some(path.to_owned()),
+                }
+            }
+            _ => return Err(format!("unknown option '{name}'")),
+        }
+        Ok(())
+    }
+
+    pub fn search_params(&self) -> SearchParams {
+        let exploration = match self.limit_strength {
+            // weaker settings wander further from the currently best-looking moves
+            true => 200.0 - 125.0 * self.elo_fraction(),
+            false => self.exploration as f64,
+        };
+        let blunders = match self.limit_strength {
+            true => 30.0 * (1.0 - self.elo_fraction()),
+            false => self.blunders as f64,
+        };
+        SearchParams {
+            mode: self.search_mode,
+            selection_rate: 0.3 * 100.0 / exploration,
+            uct_exploration: 0.5 * exploration / 100.0,
+            blunder_rate: blunders / 100.0,
+            qsearch_depth: self.qsearch_depth,
+            weights: self.weights.clone(),
+            tablebase: self.tablebase.clone(),
+        }
+    }
+
+    /// Maximum number of search iterations per move, if strength is being limited.
+    pub fn iteration_cap(&self) -> Option<usize> {
+        match self.limit_strength {
+            // 10 iterations at the bottom of the range, doubling every 200 Elo
+            true => Some((10.0 * 2f64.powf(self.elo_fraction() * 8.0)) as usize),
+            false => Some(self.iterations).filter(|&i| i != 0),
+        }
+    }
+
+    /// Random number generator for a search, seeded with `Seed` unless that is 0.
+    pub fn rng(&self) -> StdRng {
+        self.thread_rng(0)
+    }
+
+    /// Like `rng`, but for the given search thread, so that each thread makes different choices.
+    pub fn thread_rng(&self, thread: usize) -> StdRng {
+        match self.seed {
+            0 => StdRng::from_entropy(),
+            seed => StdRng::seed_from_u64(
+                seed.wrapping_add((thread as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+            ),
+        }
+    }
+
+    fn elo_fraction(&self) -> f64 {
+        (self.elo - MIN_ELO) as f64 / (MAX_ELO - MIN_ELO) as f64
+    }
+}
+
+fn parse_check(value: &str) -> Result<bool, String> {
+    match &*value.to_ascii_lowercase() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("expected 'true' or 'false', found '{value}'")),
+    }
+}
+
+fn parse_spin(value: &str, min: i64, max: i64) -> Result<i64, String> {
+    match value.parse::<i64>() {
+        Ok(v) if (min..=max).contains(&v) => Ok(v),
+        _ => Err(format!(
+            "expected an integer from {min} to {max}, found '{value}'"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn thread_rng_is_seeded_per_thread() {
+        let options = Options {
+            seed: 3,
+            ..Options::default()
+        };
+        let first = options.thread_rng(5).gen::<u64>();
+        assert_eq!(options.thread_rng(5).gen::<u64>(), first);
+        assert_ne!(options.thread_rng(4).gen::<u64>(), first);
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt::Write;
+
+use cozy_chess::{Board, Move, Piece};
+
+/// Formats `mv`, which must be legal on `board`, in standard algebraic notation.
+pub fn to_san(board: &Board, mv: Move) -> String {
+    let piece = board.piece_on(mv.from).unwrap();
+    let mut san = String::new();
+
+    if board.colors(board.side_to_move()).has(mv.to) {
+        // cozy-chess castles by capturing our own rook
+        san += match mv.to.file() > mv.from.file() {
+            true => "O-O",
+            false => "O-O-O",
+        };
+    } else {
+        let capture =
+            board.occupied().has(mv.to) || (piece == Piece::Pawn && mv.from.file() != mv.to.file());
+        if piece == Piece::Pawn {
+            if capture {
+                san.push(mv.from.file().into());
+            }
+        } else {
+            san.push(piece_char(piece));
+            let mut rivals = vec![];
+            board.generate_moves_for(board.pieces(piece), |mvs| {
+                rivals.extend(
+                    mvs.into_iter()
+                        .filter(|m| m.to == mv.to && m.from != mv.from),
+                );
+                false
+            });
+            if !rivals.is_empty() {
+                if rivals.iter().all(|m| m.from.file() != mv.from.file()) {
+                    san.push(mv.from.file().into());
+                } else if rivals.iter().all(|m| m.from.rank() != mv.from.rank()) {
+                    san.push(mv.from.rank().into());
+                } else {
+                    write!(san, "{}", mv.from).unwrap();
+                }
+            }
+        }
+        if capture {
+            san.push('x');
+        }
+        write!(san, "{}", mv.to).unwrap();
+        if let Some(promotion) = mv.promotion {
+            write!(san, "={}", piece_char(promotion)).unwrap();
+        }
+    }
+
+    let mut after = board.clone();
+    after.play_unchecked(mv);
+    if !after.checkers().is_empty() {
+        san.push(match after.generate_moves(|_| true) {
+            true => '+',
+            false => '#',
+        });
+    }
+    san
+}
+
+/// Formats a sequence of moves from `board` in standard algebraic notation, separated by spaces.
+pub fn san_line(board: &Board, moves: &[Move]) -> String {
+    let mut board = board.clone();
+    let mut line = vec![];
+    for &mv in moves {
+        line.push(to_san(&board, mv));
+        board.play_unchecked(mv);
+    }
+    line.join(" ")
+}
+
+fn piece_char(piece: Piece) -> char {
+    char::from(piece).to_ascii_uppercase()
+}
+
+/// Formats a game starting from `board` as PGN movetext, wrapped to fit in 80 columns. Each move
+/// may be followed by a comment.
+pub fn movetext(board: &Board, moves: &[(Move, Option<String>)], result: &str) -> String {
+    let mut board = board.clone();
+    let mut tokens = vec![];
+    for (i, (mv, comment)) in moves.iter().enumerate() {
+        let number = board.fullmove_number();
+        match board.side_to_move() {
+            cozy_chess::Color::White => tokens.push(format!("{number}.")),
+            // black's moves only need a number at the start or after a comment
+            _ if i == 0 || moves[i - 1].1.is_some() => tokens.push(format!("{number}...")),
+            _ => {}
+        }
+        tokens.push(to_san(&board, *mv));
+        if let Some(comment) = comment {
+            tokens.push(format!("{{{comment}}}"));
+        }
+        board.play_unchecked(*mv);
+    }
+    tokens.push(result.to_owned());
+
+    let mut text = String::new();
+    let mut line_len = 0;
+    for token in tokens {
+        if line_len > 0 && line_len + 1 + token.len() > 79 {
+            text.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            text.push(' ');
+            line_len += 1;
+        }
+        line_len += token.len();
+        text += &token;
+    }
+    text.push('\n');
+    text
+}
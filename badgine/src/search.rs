@@ -1,33 +1,142 @@
-use cozy_chess::{Board, GameStatus, Move, Piece};
-use nohash::IntSet;
+use std::sync::Arc;
+
+use cozy_chess::{Board, GameStatus, Move, Piece, Rank, Square};
 use rand::prelude::*;
 
-use crate::eval::evaluate;
+use crate::blunder;
+use crate::eval::{evaluate, Weights};
+use crate::tablebase::Tablebase;
+use crate::tt::TranspositionTable;
+
+/// How the search picks which child to descend into.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SearchMode {
+    /// Picks children at random, favoring the ones with the best eval.
+    Random,
+    /// Monte Carlo tree search, picking children by UCT.
+    Mcts,
+}
+
+pub struct SearchParams {
+    pub mode: SearchMode,
+    pub selection_rate: f64,
+    /// Exploration constant for UCT.
+    pub uct_exploration: f64,
+    pub blunder_rate: f64,
+    pub qsearch_depth: usize,
+    pub weights: Weights,
+    pub tablebase: Option<Arc<Tablebase>>,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        SearchParams {
+            mode: SearchMode::Random,
+            selection_rate: 0.3,
+            uct_exploration: 0.5,
+            blunder_rate: 0.0,
+            qsearch_depth: 0,
+            weights: Weights::default(),
+            tablebase: None,
+        }
+    }
+}
+
+/// Positions leading up to the one being searched, for detecting repetitions.
+#[derive(Clone, Default)]
+pub struct History {
+    /// Positions from the game before the search root, oldest first.
+    game: Vec<u64>,
+    /// Positions on the path from the search root to the current node.
+    path: Vec<u64>,
+}
+
+impl History {
+    /// Records a position played in the game, before the search starts.
+    pub fn push_game(&mut self, hash: u64) {
+        self.game.push(hash);
+    }
+
+    fn push(&mut self, hash: u64) {
+        self.path.push(hash);
+    }
+
+    fn pop(&mut self) {
+        self.path.pop();
+    }
+
+    /// Whether `board` is a draw by repetition. Revisiting a position from the search path is
+    /// scored as a draw straight away, since whoever allowed it could repeat it again, while
+    /// positions from the game only count once they have occurred twice before. Positions from
+    /// before the last capture or pawn move can't recur, so they are never looked at.
+    fn is_repetition(&self, board: &Board) -> bool {
+        let hash = board.hash();
+        let window = board.halfmove_clock() as usize;
+        if self.path.iter().rev().take(window).any(|&h| h == hash) {
+            return true;
+        }
+        let window = window.saturating_sub(self.path.len());
+        self.game
+            .iter()
+            .rev()
+            .take(window)
+            .filter(|&&h| h == hash)
+            .count()
+            >= 2
+    }
+}
 
 pub struct Node {
     eval: Eval,
     depth: usize,
+    /// Number of times this node has been searched, counting its creation.
+    visits: u64,
+    /// Sum of the win probabilities for the side to move over all visits.
+    total: f64,
     children: Option<Vec<(Move, Node)>>,
 }
 
 impl Node {
-    pub fn new(board: &Board, history: &mut IntSet<u64>) -> Self {
-        let (eval, children) = match board.status() {
+    pub fn new(
+        board: &Board,
+        history: &mut History,
+        params: &SearchParams,
+        tt: &mut TranspositionTable,
+    ) -> Self {
+        // the root still needs a move, even if the opponent could claim a draw there
+        let claim_draws = !history.path.is_empty();
+        let (eval, children) = match status(board, claim_draws) {
             GameStatus::Won => (Eval::Lost(0), Some(vec![])),
             GameStatus::Drawn => (Eval::Value(0), Some(vec![])),
             GameStatus::Ongoing => {
-                if history.contains(&board.hash()) {
-                    (Eval::Value(0), Some(vec![]))
-                } else if draw_by_insufficient_material(board) {
+                if (claim_draws && history.is_repetition(board))
+                    || draw_by_insufficient_material(board)
+                {
                     (Eval::Value(0), Some(vec![]))
+                } else if let Some(eval) = params.tablebase.as_ref().and_then(|tb| tb.probe(board))
+                {
+                    // nothing to search below a known result, except that the root needs a move
+                    (eval, claim_draws.then(Vec::new))
+                } else if let Some(entry) = tt.probe(board.hash()) {
+                    (entry.eval, None)
                 } else {
-                    (Eval::Value(evaluate(board)), None)
+                    let eval = Eval::Value(quiescence(
+                        board,
+                        -i32::MAX,
+                        i32::MAX,
+                        params.qsearch_depth,
+                        &params.weights,
+                    ));
+                    tt.store(board.hash(), eval, None, 0);
+                    (eval, None)
                 }
             }
         };
         Node {
             eval,
             depth: 0,
+            visits: 1,
+            total: win_probability(eval),
             children,
         }
     }
@@ -40,6 +149,20 @@ impl Node {
         self.depth
     }
 
+    pub fn visits(&self) -> u64 {
+        self.visits
+    }
+
+    /// Average win probability for the side to move over all visits to this node.
+    pub fn value(&self) -> f64 {
+        self.total / self.visits as f64
+    }
+
+    /// Children of an expanded node, best first.
+    pub fn children(&self) -> &[(Move, Node)] {
+        self.children.as_deref().unwrap_or(&[])
+    }
+
     pub fn get_pv(&self, pv: &mut Vec<Move>) {
         if let Some((best, next)) = self.children.as_ref().and_then(|c| c.first()) {
             pv.push(*best);
@@ -47,51 +170,236 @@ impl Node {
         }
     }
 
-    pub fn search(&mut self, mut board: Board, history: &mut IntSet<u64>) -> usize {
+    /// Finds the subtree for `target` at most `max_depth` plies below this node, which is at `board`.
+    pub fn into_descendant(self, board: &Board, target: &Board, max_depth: usize) -> Option<Node> {
+        if board.hash() == target.hash() {
+            return Some(self);
+        }
+        if max_depth == 0 {
+            return None;
+        }
+        self.children?.into_iter().find_map(|(mv, child)| {
+            let mut board = board.clone();
+            board.play_unchecked(mv);
+            child.into_descendant(&board, target, max_depth - 1)
+        })
+    }
+
+    /// Number of nodes in this subtree.
+    pub fn size(&self) -> usize {
+        1 + self.children().iter().map(|(_, n)| n.size()).sum::<usize>()
+    }
+
+    /// Frees at least `target` nodes, if the tree is big enough, by collapsing the least visited
+    /// subtrees back into leaves. Collapsed nodes keep their eval and statistics, and are expanded
+    /// again if the search comes back to them. Returns the number of nodes freed.
+    pub fn prune(&mut self, target: usize) -> usize {
+        let mut freed = 0;
+        // every expanded node has been visited at least twice
+        let mut min_visits = 4;
+        while freed < target && min_visits <= self.visits {
+            freed += self.collapse_below(min_visits);
+            min_visits *= 2;
+        }
+        freed
+    }
+
+    fn collapse_below(&mut self, min_visits: u64) -> usize {
+        let Some(children) = self.children.as_mut() else {
+            return 0;
+        };
+        let mut freed = 0;
+        for (_, child) in children {
+            if child.visits < min_visits && !child.children().is_empty() {
+                freed += child.size() - 1;
+                child.children = None;
+            } else {
+                freed += child.collapse_below(min_visits);
+            }
+        }
+        freed
+    }
+
+    /// Adds the root statistics of `other`, an independent search of the same position, to this
+    /// node. Each move keeps whichever of the two subtrees was visited more, and moves this tree
+    /// overlooked stay overlooked.
+    pub fn merge(&mut self, other: Node, mode: SearchMode) {
+        let (Some(children), Some(others)) = (self.children.as_mut(), other.children) else {
+            return;
+        };
+        if children.is_empty() {
+            return;
+        }
+        for (mv, theirs) in others {
+            let Some((_, ours)) = children.iter_mut().find(|(m, _)| *m == mv) else {
+                continue;
+            };
+            let visits = ours.visits + theirs.visits;
+            let total = ours.total + theirs.total;
+            if theirs.visits > ours.visits {
+                *ours = theirs;
+            }
+            ours.visits = visits;
+            ours.total = total;
+        }
+        self.visits += other.visits;
+        self.total += other.total;
+        self.depth = self.depth.max(other.depth);
+        sort_children(children, mode);
+        self.eval = -children[0].1.eval.count_time();
+    }
+
+    /// Runs one iteration of the search from this node. Returns the number of new nodes and the
+    /// move that was explored, or `None` if this node was expanded (or is terminal) instead.
+    pub fn search(
+        &mut self,
+        board: Board,
+        history: &mut History,
+        params: &SearchParams,
+        tt: &mut TranspositionTable,
+        rng: &mut impl Rng,
+    ) -> (usize, Option<Move>) {
+        let (new_nodes, mv, _) = self.visit(board, history, params, tt, rng);
+        (new_nodes, mv)
+    }
+
+    /// Like `search`, but also returns the win probability for the side to move that this visit
+    /// found, to be backed up into the statistics of the nodes above.
+    fn visit(
+        &mut self,
+        mut board: Board,
+        history: &mut History,
+        params: &SearchParams,
+        tt: &mut TranspositionTable,
+        rng: &mut impl Rng,
+    ) -> (usize, Option<Move>, f64) {
+        let parent_visits = self.visits;
+        self.visits += 1;
         match self.children.as_mut() {
             Some(children) => {
                 if children.is_empty() {
-                    return 0;
+                    let value = win_probability(self.eval);
+                    self.total += value;
+                    return (0, None, value);
                 }
 
                 let hash = board.hash();
-                history.insert(hash);
+                history.push(hash);
 
-                let i = (-thread_rng().gen::<f64>().ln() / 0.3) as usize % children.len();
-                board.play_unchecked(children[i].0);
-                let new_nodes = children[i].1.search(board, history);
+                let i = match params.mode {
+                    SearchMode::Random => {
+                        (-rng.gen::<f64>().ln() / params.selection_rate) as usize % children.len()
+                    }
+                    SearchMode::Mcts => uct_select(children, parent_visits, params.uct_exploration),
+                };
+                let mv = children[i].0;
+                board.play_unchecked(mv);
+                let (new_nodes, _, child_value) =
+                    children[i].1.visit(board, history, params, tt, rng);
                 self.depth = self.depth.max(children[i].1.depth + 1);
+                let value = 1.0 - child_value;
+                self.total += value;
 
-                history.remove(&hash);
-                children.sort_by_key(|(_, n)| n.eval);
+                history.pop();
+                sort_children(children, params.mode);
                 self.eval = -children[0].1.eval.count_time();
+                tt.store(hash, self.eval, Some(children[0].0), self.depth);
 
-                new_nodes
+                (new_nodes, Some(mv), value)
             }
             None => {
                 let children = self.children.insert(vec![]);
+                let mut overlooked = vec![];
 
-                history.insert(board.hash());
+                history.push(board.hash());
                 board.generate_moves(|mvset| {
                     for mv in mvset {
-                        let mut board = board.clone();
-                        board.play_unchecked(mv);
-                        children.push((mv, Node::new(&board, history)));
+                        let mut child = board.clone();
+                        child.play_unchecked(mv);
+                        let gives_check = !child.checkers().is_empty();
+                        if blunder::overlooks(&board, mv, gives_check, params.blunder_rate, rng) {
+                            overlooked.push((mv, child));
+                            continue;
+                        }
+                        children.push((mv, Node::new(&child, history, params, tt)));
                     }
                     false
                 });
-                history.remove(&board.hash());
+                if children.is_empty() {
+                    // nobody overlooks every move they have
+                    for (mv, child) in overlooked {
+                        children.push((mv, Node::new(&child, history, params, tt)));
+                    }
+                }
+                history.pop();
+                children.shrink_to_fit();
 
-                children.shuffle(&mut thread_rng());
-                children.sort_by_key(|(_, n)| n.eval);
+                children.shuffle(rng);
+                // ties go to the move that was best last time we were here
+                let tt_move = tt.probe(board.hash()).and_then(|e| e.best);
+                if let Some(i) = children.iter().position(|&(mv, _)| Some(mv) == tt_move) {
+                    children.swap(0, i);
+                }
+                sort_children(children, params.mode);
                 self.eval = -children[0].1.eval.count_time();
+                tt.store(board.hash(), self.eval, Some(children[0].0), 1);
 
-                children.len()
+                // the children were only just evaluated, so take the best of them as the result
+                let value = win_probability(self.eval);
+                self.total += value;
+                (children.len(), None, value)
             }
         }
     }
 }
 
+/// Puts the best child first. In MCTS mode that is a forced mate if there is one, and otherwise
+/// the most visited move, with proven losses last.
+fn sort_children(children: &mut [(Move, Node)], mode: SearchMode) {
+    match mode {
+        SearchMode::Random => children.sort_by_key(|(_, n)| n.eval),
+        SearchMode::Mcts => children.sort_by_key(|(_, n)| match n.eval {
+            Eval::Lost(_) => (0, 0, n.eval),
+            Eval::Value(_) => (1, -(n.visits as i64), n.eval),
+            Eval::Won(_) => (2, 0, n.eval),
+        }),
+    }
+}
+
+/// Picks the child with the highest upper confidence bound on its win probability.
+fn uct_select(children: &[(Move, Node)], parent_visits: u64, exploration: f64) -> usize {
+    let log_visits = (parent_visits as f64).ln();
+    let bound = |n: &Node| 1.0 - n.value() + exploration * (log_visits / n.visits as f64).sqrt();
+    (0..children.len())
+        .max_by(|&a, &b| bound(&children[a].1).total_cmp(&bound(&children[b].1)))
+        .unwrap()
+}
+
+/// Maps an eval to the probability of the side to move winning, with 400 centipawns of advantage
+/// giving 10 to 1 odds.
+fn win_probability(eval: Eval) -> f64 {
+    match eval {
+        Eval::Won(_) => 1.0,
+        Eval::Value(v) => 1.0 / (1.0 + 10f64.powf(-v as f64 / 400.0)),
+        Eval::Lost(_) => 0.0,
+    }
+}
+
+/// Like `Board::status`, but checkmate takes precedence over the fifty-move rule, which is only
+/// applied if `claim_draws` is set.
+pub fn status(board: &Board, claim_draws: bool) -> GameStatus {
+    if board.generate_moves(|_| true) {
+        match claim_draws && board.halfmove_clock() >= 100 {
+            true => GameStatus::Drawn,
+            false => GameStatus::Ongoing,
+        }
+    } else if board.checkers().is_empty() {
+        GameStatus::Drawn
+    } else {
+        GameStatus::Won
+    }
+}
+
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Eval {
     Won(u32),
@@ -123,7 +431,7 @@ impl PartialOrd for Eval {
 impl Eq for Eval {}
 
 impl Eval {
-    fn count_time(self) -> Eval {
+    pub fn count_time(self) -> Eval {
         match self {
             Eval::Won(d) => Eval::Won(d + 1),
             Eval::Value(v) => Eval::Value(v),
@@ -154,9 +462,154 @@ impl std::fmt::Display for Eval {
     }
 }
 
-fn draw_by_insufficient_material(board: &Board) -> bool {
+/// Capture-only search to settle exchanges in progress before trusting the static eval.
+fn quiescence(board: &Board, mut alpha: i32, beta: i32, depth: usize, weights: &Weights) -> i32 {
+    let mut best = evaluate(board, weights);
+    if depth == 0 || best >= beta {
+        return best;
+    }
+    alpha = alpha.max(best);
+
+    let stm = board.side_to_move();
+    let mut pawn_targets = Rank::First.bitboard() | Rank::Eighth.bitboard();
+    if let Some(file) = board.en_passant() {
+        pawn_targets |= Square::new(file, Rank::Sixth.relative_to(stm)).bitboard();
+    }
+    let mut moves = vec![];
+    board.generate_moves(|mut mvs| {
+        mvs.to &= match mvs.piece {
+            Piece::Pawn => board.colors(!stm) | pawn_targets,
+            _ => board.colors(!stm),
+        };
+        moves.extend(mvs);
+        false
+    });
+    // most valuable victim first
+    moves.sort_by_key(|mv| std::cmp::Reverse(board.piece_on(mv.to).map(|p| p as usize)));
+
+    for mv in moves {
+        let mut child = board.clone();
+        child.play_unchecked(mv);
+        let score = -quiescence(&child, -beta, -alpha, depth - 1, weights);
+        if score > best {
+            best = score;
+            alpha = alpha.max(score);
+            if score >= beta {
+                break;
+            }
+        }
+    }
+    best
+}
+
+pub fn draw_by_insufficient_material(board: &Board) -> bool {
     board.pieces(Piece::Pawn).is_empty()
         && board.pieces(Piece::Rook).is_empty()
         && board.pieces(Piece::Queen).is_empty()
         && board.pieces(Piece::Bishop).popcnt() + board.pieces(Piece::Knight).popcnt() < 2
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays `moves` from `board`, returning the hashes of the positions before each move.
+    fn play(board: &mut Board, moves: &str) -> Vec<u64> {
+        moves
+            .split_whitespace()
+            .map(|mv| {
+                let hash = board.hash();
+                board.play(mv.parse().unwrap());
+                hash
+            })
+            .collect()
+    }
+
+    #[test]
+    fn twofold_repetition_in_search_path() {
+        let mut board = Board::default();
+        let path = play(&mut board, "g1f3 g8f6 f3g1 f6g8");
+        let history = History { game: vec![], path };
+        assert!(history.is_repetition(&board));
+    }
+
+    #[test]
+    fn threefold_repetition_in_game() {
+        let mut board = Board::default();
+        let game = play(&mut board, "g1f3 g8f6 f3g1 f6g8");
+        let history = History {
+            game: game.clone(),
+            path: vec![],
+        };
+        // only the second occurrence, which doesn't end the game yet
+        assert!(!history.is_repetition(&board));
+
+        let mut game = game;
+        game.extend(play(&mut board, "g1f3 g8f6 f3g1 f6g8"));
+        let history = History { game, path: vec![] };
+        assert!(history.is_repetition(&board));
+    }
+
+    #[test]
+    fn no_repetition_across_pawn_move() {
+        // the same position with different numbers of plies since the last capture or pawn move
+        let board = |halfmove: u8| {
+            let fen =
+                format!("rnbqkbnr/pppppppp/8/8/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - {halfmove} 2");
+            Board::from_fen(&fen, false).unwrap()
+        };
+        let hash = board(0).hash();
+        let history = History {
+            game: vec![hash, hash, 1, 2, 3],
+            path: vec![],
+        };
+        assert!(!history.is_repetition(&board(3)));
+        assert!(history.is_repetition(&board(5)));
+
+        // the search path takes up part of the window
+        let history = History {
+            game: vec![hash, hash, 1],
+            path: vec![2, 3],
+        };
+        assert!(!history.is_repetition(&board(3)));
+        assert!(history.is_repetition(&board(5)));
+
+        let history = History {
+            game: vec![],
+            path: vec![hash, 1, 2],
+        };
+        assert!(!history.is_repetition(&board(2)));
+        assert!(history.is_repetition(&board(3)));
+    }
+
+    #[test]
+    fn checkmate_beats_fifty_move_rule() {
+        let mated = Board::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80", false).unwrap();
+        assert_eq!(status(&mated, true), GameStatus::Won);
+        assert_eq!(status(&mated, false), GameStatus::Won);
+
+        let quiet = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 b - - 100 80", false).unwrap();
+        assert_eq!(status(&quiet, true), GameStatus::Drawn);
+        assert_eq!(status(&quiet, false), GameStatus::Ongoing);
+    }
+
+    #[test]
+    fn prune_after_many_visits() {
+        let board = Board::default();
+        let mut history = History::default();
+        let params = SearchParams::default();
+        let mut tt = TranspositionTable::new(1024 * 1024);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut root = Node::new(&board, &mut history, &params, &mut tt);
+        for _ in 0..100 {
+            root.search(board.clone(), &mut history, &params, &mut tt, &mut rng);
+        }
+        // as after a long analysis, more visits than a u32 holds
+        root.visits = 1 << 40;
+        // everything below the root's children is collapsed
+        let size = root.size();
+        let leaves = 1 + root.children().len();
+        assert_eq!(root.prune(usize::MAX), size - leaves);
+        assert_eq!(root.size(), leaves);
+    }
+}
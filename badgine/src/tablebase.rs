@@ -0,0 +1,99 @@
+use cozy_chess::{Board, Color};
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess};
+use shakmaty_syzygy::{AmbiguousWdl, Tablebase as Tables};
+
+use crate::search::Eval;
+
+/// Score of a tablebase win before its DTZ distance is taken off. Wins sort below every mate the
+/// search finds, since Syzygy doesn't know the distance to mate, but above any evaluation.
+pub const TB_WIN: i32 = 20_000;
+
+/// Syzygy endgame tablebases.
+pub struct Tablebase {
+    tables: Tables<Chess>,
+}
+
+impl Tablebase {
+    /// Loads the tables in `path`, which can list several directories separated like `PATH`.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let mut tables = Tables::new();
+        let mut found = 0;
+        for dir in std::env::split_paths(path) {
+            found += tables
+                .add_directory(&dir)
+                .map_err(|e| format!("{}: {e}", dir.display()))?;
+        }
+        if found == 0 {
+            return Err("no tables found".to_owned());
+        }
+        Ok(Tablebase { tables })
+    }
+
+    /// Looks up `board` in the tables. Wins are scored `TB_WIN` less the DTZ distance, the number
+    /// of plies to the next capture or pawn move, and losses the negation of that. Wins that the
+    /// fifty-move rule turns into draws are draws. Returns `None` if the position isn't covered.
+    pub fn probe(&self, board: &Board) -> Option<Eval> {
+        if board.occupied().popcnt() as usize > self.tables.max_pieces() {
+            return None;
+        }
+        let castling = Color::ALL.iter().any(|&c| {
+            let rights = board.castle_rights(c);
+            rights.short.is_some() || rights.long.is_some()
+        });
+        if castling {
+            return None;
+        }
+
+        let pos: Chess = Fen::from_ascii(board.to_string().as_bytes())
+            .ok()?
+            .into_position(CastlingMode::Standard)
+            .ok()?;
+        let distance = || {
+            let dtz = self.tables.probe_dtz(&pos).ok()?.ignore_rounding();
+            Some(i32::from(dtz).abs())
+        };
+        match self.tables.probe_wdl(&pos).ok()? {
+            AmbiguousWdl::Win | AmbiguousWdl::MaybeWin => Some(Eval::Value(TB_WIN - distance()?)),
+            AmbiguousWdl::Loss | AmbiguousWdl::MaybeLoss => Some(Eval::Value(distance()? - TB_WIN)),
+            AmbiguousWdl::CursedWin | AmbiguousWdl::Draw | AmbiguousWdl::BlessedLoss => {
+                Some(Eval::Value(0))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_needs_tables() {
+        let dir = std::env::temp_dir().join("badgine-no-tables");
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(Tablebase::load(dir.to_str().unwrap()).is_err());
+    }
+
+    /// Probes positions from the shakmaty-syzygy test suite. Needs the 3 and 4 piece tables, in
+    /// the directory named by the `BADGINE_SYZYGY_PATH` environment variable.
+    #[test]
+    #[ignore = "needs Syzygy tables"]
+    fn probe_small_tables() {
+        let path = std::env::var("BADGINE_SYZYGY_PATH").expect("BADGINE_SYZYGY_PATH is not set");
+        let tablebase = Tablebase::load(&path).unwrap();
+        let positions = [
+            ("q7/k2K4/5B2/8/8/8/8/8 b - - 0 1", Eval::Value(TB_WIN - 19)),
+            ("3B4/K7/8/k3N3/8/8/8/8 b - - 0 1", Eval::Value(53 - TB_WIN)),
+            ("8/Q7/8/1K6/2B5/1k6/8/8 b - - 0 1", Eval::Value(8 - TB_WIN)),
+            ("8/8/8/8/7r/6q1/2K5/6k1 w - - 0 1", Eval::Value(4 - TB_WIN)),
+            ("R7/8/6k1/8/8/8/2K4r/8 w - - 0 1", Eval::Value(0)),
+            // the fifty-move rule catches up with the win first
+            ("q7/k2K4/5B2/8/8/8/8/8 b - - 90 60", Eval::Value(0)),
+        ];
+        for (fen, eval) in positions {
+            let board = Board::from_fen(fen, false).unwrap();
+            assert_eq!(tablebase.probe(&board), Some(eval), "{fen}");
+        }
+        assert_eq!(tablebase.probe(&Board::default()), None);
+    }
+}
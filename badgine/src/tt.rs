@@ -0,0 +1,52 @@
+use cozy_chess::Move;
+
+use crate::search::Eval;
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    key: u64,
+    pub eval: Eval,
+    pub best: Option<Move>,
+    pub depth: u16,
+}
+
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+}
+
+impl TranspositionTable {
+    pub fn new(bytes: usize) -> Self {
+        let len = (bytes / std::mem::size_of::<Option<Entry>>()).max(1);
+        TranspositionTable {
+            entries: vec![None; len],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+
+    pub fn probe(&self, hash: u64) -> Option<Entry> {
+        self.entries[self.index(hash)].filter(|e| e.key == hash)
+    }
+
+    pub fn store(&mut self, hash: u64, eval: Eval, best: Option<Move>, depth: usize) {
+        let depth = depth.min(u16::MAX as usize) as u16;
+        let index = self.index(hash);
+        let slot = &mut self.entries[index];
+        // prefer keeping deeper results for the same position, but always replace other positions
+        if slot.is_some_and(|e| e.key == hash && e.depth > depth) {
+            return;
+        }
+        *slot = Some(Entry {
+            key: hash,
+            eval,
+            best,
+            depth,
+        });
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        ((hash as u128 * self.entries.len() as u128) >> 64) as usize
+    }
+}
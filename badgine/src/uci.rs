@@ -0,0 +1,28 @@
+//! Move notation for talking to UCI GUIs and engines.
+
+use cozy_chess::{Board, File, Move, Piece, Square};
+
+/// Converts a castling move from king-takes-rook, as cozy-chess plays it, to the king's two-square
+/// move that standard UCI uses. Chess960 sends king-takes-rook as is.
+pub fn to_uci_castling(board: &Board, mut mv: Move, chess960: bool) -> Move {
+    if !chess960 && board.color_on(mv.from) == board.color_on(mv.to) {
+        if mv.to.file() > mv.from.file() {
+            mv.to = Square::new(File::G, mv.to.rank());
+        } else {
+            mv.to = Square::new(File::C, mv.to.rank());
+        }
+    }
+    mv
+}
+
+/// The inverse of `to_uci_castling`.
+pub fn from_uci_castling(board: &Board, mut mv: Move, chess960: bool) -> Move {
+    if !chess960 && mv.from.file() == File::E && board.piece_on(mv.from) == Some(Piece::King) {
+        if mv.to.file() == File::G {
+            mv.to = Square::new(File::H, mv.to.rank());
+        } else if mv.to.file() == File::C {
+            mv.to = Square::new(File::A, mv.to.rank());
+        }
+    }
+    mv
+}